@@ -0,0 +1,92 @@
+use std::io::{self, Write};
+
+use ::chess_br::world::World;
+use ::chess_br::world::command::{parse_action, COMMANDS};
+use ::chess_br::world::config::GameConfig;
+use ::chess_br::world::display::{print_world, print_air};
+use ::chess_br::world::player::{Player, PlayerState};
+
+use crate::print_scores;
+
+fn redraw(world: &World) {
+    print!("\x1B[2J\x1B[H");
+    println!("Tick {}, {}", world.tick(), world.fog.status());
+    print_world(world);
+
+    let players = world.players_by_score();
+    let mut heights: Vec<u16> = players
+        .iter()
+        .filter_map(|p| match p.state {
+            PlayerState::Falling(h, _coord) => Some(h),
+            _ => None,
+        })
+        .collect();
+    heights.sort_unstable_by(|a, b| b.cmp(a));
+    heights.dedup();
+    for height in heights {
+        print!("\nHeight {}", height);
+        print_air(world.fog.shape(), &players, height);
+    }
+
+    println!();
+    for record in world.historian().by_ticks(world.tick().saturating_sub(1), world.tick()) {
+        println!("{}", record.summarize());
+    }
+    print_scores(world);
+}
+
+fn prompt(player: &Player) -> Option<String> {
+    print!("{} {:?} > ", player.user_name, player.state);
+    io::stdout().flush().ok()?;
+    let mut line = String::new();
+    match io::stdin().read_line(&mut line) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(String::from(line.trim())),
+    }
+}
+
+// Every living player gets a turn to queue an action, then the world ticks
+pub fn play(mut config: GameConfig) {
+    config.settings.verbose = false;
+    let mut world = config.spawn();
+    let mut uids: Vec<u16> = world.players.keys().copied().collect();
+    uids.sort_unstable();
+    if uids.is_empty() {
+        println!("No players to play with");
+        return;
+    }
+
+    while world.is_alive() {
+        redraw(&world);
+        for uid in uids.iter() {
+            loop {
+                let player = world.players[uid].clone();
+                if !player.state.is_alive() { break; }
+                let line = match prompt(&player) {
+                    Some(line) => line,
+                    None => return,
+                };
+                match line.as_str() {
+                    "" => break,
+                    "quit" => return,
+                    "help" => println!("{} | moves | quit, empty to wait", COMMANDS),
+                    "moves" => {
+                        for (from, to) in world.legal_moves(*uid) {
+                            print!("{},{} {},{}  ", from.x, from.y, to.x, to.y);
+                        }
+                        println!();
+                    },
+                    _ => match parse_action(*uid, &line) {
+                        Ok(action) => match world.request_action(action) {
+                            Ok(()) => break,
+                            Err(err) => println!("{:?} rejected: {:?}", action, err),
+                        },
+                        Err(err) => println!("{}", err),
+                    },
+                }
+            }
+        }
+        world.do_tick();
+    }
+    redraw(&world);
+}
@@ -1,3 +1,4 @@
 pub mod world;
+pub mod server;
 
-pub fn todo() {}
\ No newline at end of file
+pub fn todo() {}
@@ -1,9 +1,19 @@
-use ::chess_br::world::spawn;
+use std::env;
+use std::net::TcpListener;
+use std::process;
+use std::str::FromStr;
+use std::sync::mpsc;
+
+use ::chess_br::server::{GameServer, ServerSettings};
+use ::chess_br::server::lobby::{FinishedGame, Lobby};
+use ::chess_br::server::{tcp, ws, Inbound};
 use ::chess_br::world::{World, Action};
-use ::chess_br::world::direction::Direction;
-use ::chess_br::world::position::{Coord, Positional, Offset};
+use ::chess_br::world::config::GameConfig;
+use ::chess_br::world::position::Offset;
 use ::chess_br::world::display::{print_board_pair, print_air};
 
+mod hotseat;
+
 fn print_scores(world: &World) {
     for (idx, player) in world.players_by_score().iter().enumerate() {
         let (rank, score, name) = player.in_game_info();
@@ -11,21 +21,139 @@ fn print_scores(world: &World) {
     }
 }
 
+fn request(world: &mut World, action: Action) {
+    if let Err(err) = world.request_action(action) {
+        println!("{:?} rejected: {:?}", action, err);
+    }
+}
+
+const USAGE: &str = "Usage: chess_br [play | server | lobby] [--config FILE] [--size WxH] [--zones N] [--player NAME]... [--seed N] [--move-range N] [--zone-shape rects|growth] [--addr HOST:PORT] [--ws-addr HOST:PORT] [--tick-ms N] [--min-players N] [--spectator-delay N]";
+
+fn parse<T: FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("Bad value {} for {}", value, flag))
+}
+
+fn parse_args(args: &[String], server: &mut ServerSettings) -> Result<GameConfig, String> {
+    // Flags override the config file no matter their order
+    let mut config = match args.iter().position(|arg| arg == "--config") {
+        Some(idx) => match args.get(idx + 1) {
+            Some(path) => GameConfig::load(path).map_err(|err| err.to_string())?,
+            None => return Err(String::from("Missing value for --config")),
+        },
+        None => GameConfig::new(),
+    };
+    let mut players = Vec::new();
+    for pair in args.chunks(2) {
+        let (flag, value) = match pair {
+            [flag, value] => (flag.as_str(), value.as_str()),
+            _ => return Err(format!("Missing value for {}", pair[0])),
+        };
+        match flag {
+            "--config" => (),
+            "--size" => {
+                let (width, height) = value.split_once('x').ok_or(format!("Bad value {} for {}", value, flag))?;
+                config.width = parse(flag, width)?;
+                config.height = parse(flag, height)?;
+            },
+            "--zones" => config.zones = parse(flag, value)?,
+            "--player" => players.push(String::from(value)),
+            "--seed" => config.seed = Some(parse(flag, value)?),
+            "--move-range" => config.settings.move_range_limit = parse(flag, value)?,
+            "--zone-shape" => config.settings.zone_shape = parse(flag, value)?,
+            "--addr" => server.addr = String::from(value),
+            "--ws-addr" => server.ws_addr = Some(String::from(value)),
+            "--tick-ms" => server.tick_ms = parse(flag, value)?,
+            "--min-players" => server.min_players = parse(flag, value)?,
+            "--spectator-delay" => server.spectator_delay = parse(flag, value)?,
+            _ => return Err(format!("Unknown flag {}", flag)),
+        }
+    }
+    if !players.is_empty() {
+        config.players = players;
+    }
+    config.validate().map_err(|err| err.to_string())?;
+    Ok(config)
+}
+
+fn bind(addr: &str) -> TcpListener {
+    match TcpListener::bind(addr) {
+        Ok(listener) => listener,
+        Err(err) => {
+            eprintln!("Cannot listen on {}: {}", addr, err);
+            process::exit(1);
+        },
+    }
+}
+
+fn listen(settings: &ServerSettings) -> mpsc::Receiver<Inbound> {
+    let (inbox, receiver) = mpsc::channel();
+    tcp::listen(bind(&settings.addr), inbox.clone());
+    println!("Listening on {}", settings.addr);
+    if let Some(ws_addr) = &settings.ws_addr {
+        ws::listen(bind(ws_addr), inbox.clone());
+        println!("WebSocket on {}", ws_addr);
+    }
+    receiver
+}
+
+fn serve(config: GameConfig, settings: ServerSettings) {
+    let receiver = listen(&settings);
+    let mut server = GameServer::new(&config, settings);
+    println!("Seed {}", server.world().seed());
+    server.run(receiver);
+    print_scores(server.world());
+}
+
+fn report(game: &FinishedGame) {
+    println!("Game {} over after {} ticks, seed {}", game.game_id, game.ticks, game.seed);
+    for rank in game.ranking.iter() {
+        println!("{}.\tScore {}\t{}", rank.rank, rank.score, rank.game_name);
+    }
+}
+
+fn host_lobby(config: GameConfig, settings: ServerSettings) {
+    let receiver = listen(&settings);
+    let mut lobby = Lobby::new(&config, settings, report);
+    println!("Games start with {} players", lobby.settings().min_players);
+    lobby.run(receiver);
+}
+
 fn main() {
-    let mut world = spawn(
-        Coord{x: 42, y: 16},
-        4,
-        &vec![String::from("Player 1"), String::from("Player 2")]
-    );
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", USAGE);
+        return;
+    }
+    let mode = match args.first().map(|arg| arg.as_str()) {
+        Some("play") | Some("server") | Some("lobby") => Some(args.remove(0)),
+        _ => None,
+    };
+    let mut server = ServerSettings::new();
+    let config = match parse_args(&args, &mut server) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            process::exit(1);
+        },
+    };
+    match mode.as_deref() {
+        Some("play") => return hotseat::play(config),
+        Some("server") => return serve(config, server),
+        Some("lobby") => return host_lobby(config, server),
+        _ => (),
+    }
+
+    let mut world = config.spawn();
+    println!("Seed {}", world.seed());
     println!("{} flyers", world.flyers_count());
     for _ in 0..8 {
         world.do_tick();
     }
-    world.request_action(Action::Drop(1));
+    request(&mut world, Action::Drop(1));
     world.do_tick();
     print_air(world.fog.shape(), &world.players_by_score(), 10);
-    world.request_action(Action::Fly(1, Offset{x: -1, y: 0}));
-    world.request_action(Action::Drop(2));
+    request(&mut world, Action::Fly(1, Offset{x: -1, y: 0}));
+    request(&mut world, Action::Drop(2));
     world.do_tick();
 
     print_air(world.fog.shape(), &world.players_by_score(), 10);
@@ -33,11 +161,11 @@ fn main() {
     while world.airborne_count() > 0 {
         world.do_tick()
     }
-    let p2 = world.player_positions(2);
-    let king = p2[0];
-    let to = king.1.translate_direction(Direction::North);
-
-    world.request_action(Action::Move(2, p2[0].1.clone(), to));
+    let moves = world.legal_moves(2);
+    println!("{} legal moves", moves.len());
+    if let Some((from, to)) = moves.first() {
+        request(&mut world, Action::Move(2, *from, *to));
+    }
     world.do_tick();
     print_board_pair(&world.pieces_map, &world.fog.zones);
 
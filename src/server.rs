@@ -0,0 +1,391 @@
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
+use crate::world::World;
+use crate::world::config::GameConfig;
+use crate::world::observation::{Observation, SpectatorView};
+use crate::world::player::GamerNamer;
+use crate::world::replay::Replay;
+use crate::server::protocol::{ClientMessage, ServerMessage, Ranking};
+
+pub mod protocol;
+pub mod lobby;
+pub mod tcp;
+pub mod ws;
+
+static CONNECTION_ID: AtomicUsize = AtomicUsize::new(1);
+
+pub fn next_connection_id() -> usize {
+    CONNECTION_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+pub trait Outbound: Send {
+    fn send(&mut self, message: &ServerMessage) -> io::Result<()>;
+
+    fn close(&mut self) {}
+}
+
+pub enum Inbound {
+    Connected(usize, Box<dyn Outbound>),
+    Message(usize, ClientMessage),
+    Invalid(usize, String),
+    Disconnected(usize),
+}
+
+// Anything driven by the server clock, a single game or a lobby of games
+pub trait Session {
+    fn handle(&mut self, inbound: Inbound);
+    fn tick(&mut self);
+    fn is_over(&self) -> bool;
+}
+
+// Ticks the session on a fixed clock and handles inbound messages in between
+pub fn run_clock<S: Session>(session: &mut S, tick_ms: u64, inbox: Receiver<Inbound>) {
+    let tick = Duration::from_millis(tick_ms);
+    let mut deadline = Instant::now() + tick;
+    while !session.is_over() {
+        let now = Instant::now();
+        if now >= deadline {
+            session.tick();
+            // A slow tick moves the clock on instead of bursting to catch up
+            deadline = (deadline + tick).max(Instant::now());
+            continue;
+        }
+        match inbox.recv_timeout(deadline - now) {
+            Ok(inbound) => session.handle(inbound),
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+    }
+}
+
+// Fewer could never see the game end
+pub const MIN_PLAYERS: usize = 2;
+
+#[derive(Clone)]
+pub struct ServerSettings {
+    pub addr: String,
+    // Browser clients connect here, next to the line protocol
+    pub ws_addr: Option<String>,
+    pub tick_ms: u64,
+    pub min_players: usize,
+    // Ticks spectators lag behind, so they cannot ghost for players
+    pub spectator_delay: usize,
+}
+
+impl ServerSettings {
+    pub fn new() -> ServerSettings {
+        ServerSettings{
+            addr: String::from("127.0.0.1:4242"),
+            ws_addr: None,
+            tick_ms: 1000,
+            min_players: MIN_PLAYERS,
+            spectator_delay: 5,
+        }
+    }
+
+    // Raises settings that would start games nobody can finish
+    pub fn checked(mut self) -> ServerSettings {
+        self.min_players = self.min_players.max(MIN_PLAYERS);
+        self
+    }
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        ServerSettings::new()
+    }
+}
+
+struct Connection {
+    outbound: Box<dyn Outbound>,
+    player_id: Option<u16>,
+    watching: bool,
+    last: Option<Observation>,
+}
+
+pub struct GameServer {
+    world: World,
+    initial: Option<World>,
+    namer: GamerNamer,
+    settings: ServerSettings,
+    connections: HashMap<usize, Connection>,
+    feed: VecDeque<SpectatorView>,
+}
+
+fn error(message: &str) -> ServerMessage {
+    ServerMessage::Error{message: String::from(message)}
+}
+
+impl GameServer {
+    pub fn new(config: &GameConfig, settings: ServerSettings) -> Self {
+        // Players join over the network instead of coming from the config
+        let mut config = config.clone();
+        config.players.clear();
+        let world = config.spawn();
+        let namer = GamerNamer::new(&mut StdRng::seed_from_u64(world.seed()));
+        GameServer{
+            world,
+            initial: None,
+            namer,
+            settings: settings.checked(),
+            connections: HashMap::new(),
+            feed: VecDeque::new(),
+        }
+    }
+
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    pub fn started(&self) -> bool {
+        self.initial.is_some()
+    }
+
+    // Fixes the players, later joins are refused
+    pub fn start(&mut self) {
+        if self.started() { return; }
+        self.initial = Some(self.world.clone());
+    }
+
+    pub fn is_over(&self) -> bool {
+        !self.world.is_alive()
+    }
+
+    pub fn replay(&self) -> Option<Replay> {
+        self.initial.as_ref().map(|initial| Replay::new(initial, &self.world))
+    }
+
+    // Final placements first, players still in the game (rank 0) last
+    pub fn ranking(&self) -> Vec<Ranking> {
+        let mut ranking: Vec<Ranking> = self.world.players_by_score()
+            .iter()
+            .map(|player| {
+                let (rank, score, game_name) = player.in_game_info();
+                Ranking{player_id: player.player_id, game_name, rank, score}
+            })
+            .collect();
+        ranking.sort_by_key(|rank| if rank.rank == 0 { u16::MAX } else { rank.rank });
+        ranking
+    }
+
+    fn send(&mut self, id: usize, message: &ServerMessage) {
+        let failed = match self.connections.get_mut(&id) {
+            Some(connection) => connection.outbound.send(message).is_err(),
+            None => false,
+        };
+        if failed {
+            // Closing also ends the reader, which reports the disconnect
+            if let Some(mut connection) = self.leave(id) {
+                connection.outbound.close();
+            }
+        }
+    }
+
+    // Players leaving before the start are not left in the game uncontrolled
+    fn leave(&mut self, id: usize) -> Option<Connection> {
+        let connection = self.connections.remove(&id)?;
+        if let (Some(player_id), false) = (connection.player_id, self.started()) {
+            self.world.remove_player(player_id);
+        }
+        Some(connection)
+    }
+
+    pub fn handle(&mut self, inbound: Inbound) {
+        match inbound {
+            Inbound::Connected(id, outbound) => {
+                self.connections.insert(id, Connection{outbound, player_id: None, watching: false, last: None});
+            },
+            Inbound::Disconnected(id) => {
+                self.leave(id);
+            },
+            Inbound::Invalid(id, message) => self.send(id, &ServerMessage::Error{message}),
+            Inbound::Message(id, ClientMessage::Quit) => {
+                if let Some(mut connection) = self.leave(id) {
+                    connection.outbound.close();
+                }
+            },
+            Inbound::Message(id, message) => {
+                let reply = self.reply(id, message);
+                self.send(id, &reply);
+            },
+        }
+    }
+
+    fn reply(&mut self, id: usize, message: ClientMessage) -> ServerMessage {
+        let (player_id, watching) = match self.connections.get(&id) {
+            Some(connection) => (connection.player_id, connection.watching),
+            None => return error("Unknown connection"),
+        };
+        match (message, player_id) {
+            (_, None) if watching => error("Spectators cannot play"),
+            (ClientMessage::Watch{..}, None) => {
+                self.connections.get_mut(&id).unwrap().watching = true;
+                ServerMessage::Watching{delay: self.settings.spectator_delay}
+            },
+            (ClientMessage::Watch{..}, Some(_)) => error("Players cannot watch"),
+            (ClientMessage::Join{name}, None) => {
+                if self.started() { return error("Game already started"); }
+                let player_id = self.world.add_player(name, &mut self.namer);
+                self.connections.get_mut(&id).unwrap().player_id = Some(player_id);
+                let game_name = self.world.players[&player_id].game_name.clone();
+                ServerMessage::Joined{player_id, game_name}
+            },
+            (ClientMessage::Join{..}, Some(_)) => error("Already joined"),
+            (_, None) => error("Join first"),
+            (ClientMessage::Action{action}, Some(player_id)) => {
+                match self.world.request_observed_action(action.with_user(player_id)) {
+                    Ok(()) => ServerMessage::Accepted,
+                    Err(err) => ServerMessage::Error{message: format!("{:?}", err)},
+                }
+            },
+            (ClientMessage::State, Some(player_id)) => match self.world.observation(player_id) {
+                Some(observation) => ServerMessage::State{observation},
+                None => error("Unknown player"),
+            },
+            (ClientMessage::Quit, Some(_)) => error("Already left"),
+        }
+    }
+
+    // Waits for enough players before the clock starts moving the world
+    pub fn tick(&mut self) {
+        if self.is_over() { return; }
+        if !self.started() {
+            if self.world.players.len() < self.settings.min_players { return; }
+            self.start();
+        }
+        self.world.do_tick();
+        self.broadcast();
+    }
+
+    fn broadcast(&mut self) {
+        let mut ids: Vec<usize> = self.connections.keys().copied().collect();
+        ids.sort_unstable();
+        for id in ids.iter() {
+            let connection = self.connections.get_mut(id).unwrap();
+            let player_id = match connection.player_id {
+                Some(player_id) => player_id,
+                None => continue,
+            };
+            let observation = self.world.observation(player_id).unwrap();
+            let diff = observation.diff(connection.last.as_ref());
+            connection.last = Some(observation);
+            self.send(*id, &ServerMessage::Tick{diff});
+        }
+        self.spectate(&ids);
+        if self.is_over() {
+            let game_over = ServerMessage::GameOver{ranking: self.ranking()};
+            for id in ids.iter() {
+                self.send(*id, &game_over);
+            }
+        }
+    }
+
+    // Spectators get the full view from a few ticks ago, or everything left once the game is over
+    fn spectate(&mut self, ids: &[usize]) {
+        self.feed.push_back(self.world.spectator_view());
+        let keep = if self.is_over() { 0 } else { self.settings.spectator_delay };
+        while self.feed.len() > keep {
+            let view = self.feed.pop_front().unwrap();
+            let spectate = ServerMessage::Spectate{view};
+            for id in ids.iter() {
+                if self.connections.get(id).is_some_and(|connection| connection.watching) {
+                    self.send(*id, &spectate);
+                }
+            }
+        }
+    }
+
+    // Runs the fixed clock until the game is over or every sender is gone
+    pub fn run(&mut self, inbox: Receiver<Inbound>) {
+        let tick_ms = self.settings.tick_ms;
+        run_clock(self, tick_ms, inbox);
+    }
+
+    // Hands the connections back, e.g. to the lobby once the game is over
+    pub fn into_connections(self) -> Vec<(usize, Box<dyn Outbound>)> {
+        self.connections
+            .into_iter()
+            .map(|(id, connection)| (id, connection.outbound))
+            .collect()
+    }
+}
+
+impl Session for GameServer {
+    fn handle(&mut self, inbound: Inbound) {
+        GameServer::handle(self, inbound)
+    }
+
+    fn tick(&mut self) {
+        GameServer::tick(self)
+    }
+
+    fn is_over(&self) -> bool {
+        GameServer::is_over(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Sink;
+
+    impl Outbound for Sink {
+        fn send(&mut self, _message: &ServerMessage) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn server() -> GameServer {
+        let mut config = GameConfig::new();
+        config.seed = Some(1);
+        config.settings.verbose = false;
+        GameServer::new(&config, ServerSettings::new())
+    }
+
+    fn join(server: &mut GameServer, id: usize, name: &str) {
+        server.handle(Inbound::Connected(id, Box::new(Sink)));
+        server.handle(Inbound::Message(id, ClientMessage::Join{name: String::from(name)}));
+    }
+
+    #[test]
+    fn games_need_two_players() {
+        let mut config = GameConfig::new();
+        config.settings.verbose = false;
+        let mut settings = ServerSettings::new();
+        settings.min_players = 0;
+        let mut server = GameServer::new(&config, settings);
+        server.tick();
+        assert!(!server.started());
+        join(&mut server, 1, "Ann");
+        server.tick();
+        assert!(!server.started());
+    }
+
+    #[test]
+    fn leaving_before_the_start_frees_the_seat() {
+        let mut server = server();
+        join(&mut server, 1, "Ann");
+        join(&mut server, 2, "Bo");
+        server.handle(Inbound::Disconnected(2));
+        server.tick();
+        assert!(!server.started());
+        assert_eq!(server.world().players.len(), 1);
+
+        join(&mut server, 3, "Cy");
+        server.tick();
+        assert!(server.started());
+        assert_eq!(server.world().players.len(), 2);
+
+        // Once started, players stay in the game and can still be beaten
+        server.handle(Inbound::Message(3, ClientMessage::Quit));
+        assert_eq!(server.world().players.len(), 2);
+    }
+}
@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+use std::sync::mpsc::Receiver;
+
+use crate::world::config::GameConfig;
+use crate::server::{GameServer, Inbound, Outbound, ServerSettings, Session, run_clock};
+use crate::server::protocol::{ClientMessage, ServerMessage, Ranking};
+
+pub struct FinishedGame {
+    pub game_id: usize,
+    pub seed: u64,
+    pub ticks: usize,
+    pub ranking: Vec<Ranking>,
+}
+
+fn error(message: &str) -> ServerMessage {
+    ServerMessage::Error{message: String::from(message)}
+}
+
+// Queues joining players and runs a game for every full table of them
+pub struct Lobby {
+    config: GameConfig,
+    settings: ServerSettings,
+    report: fn(&FinishedGame),
+    waiting: HashMap<usize, Box<dyn Outbound>>,
+    queue: Vec<(usize, String)>,
+    games: HashMap<usize, GameServer>,
+    routes: HashMap<usize, usize>,
+    next_game_id: usize,
+}
+
+impl Lobby {
+    pub fn new(config: &GameConfig, settings: ServerSettings, report: fn(&FinishedGame)) -> Self {
+        Lobby{
+            config: config.clone(),
+            settings: settings.checked(),
+            report,
+            waiting: HashMap::new(),
+            queue: Vec::new(),
+            games: HashMap::new(),
+            routes: HashMap::new(),
+            next_game_id: 1,
+        }
+    }
+
+    pub fn settings(&self) -> &ServerSettings {
+        &self.settings
+    }
+
+    pub fn games(&self) -> &HashMap<usize, GameServer> {
+        &self.games
+    }
+
+    pub fn queued(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn run(&mut self, inbox: Receiver<Inbound>) {
+        let tick_ms = self.settings.tick_ms;
+        run_clock(self, tick_ms, inbox);
+    }
+
+    fn send(&mut self, id: usize, message: &ServerMessage) {
+        let failed = match self.waiting.get_mut(&id) {
+            Some(outbound) => outbound.send(message).is_err(),
+            None => false,
+        };
+        if failed {
+            if let Some(mut outbound) = self.waiting.remove(&id) {
+                outbound.close();
+            }
+            self.leave(id);
+        }
+    }
+
+    fn leave(&mut self, id: usize) {
+        self.waiting.remove(&id);
+        self.queue.retain(|(queued, _)| *queued != id);
+    }
+
+    fn announce_queue(&mut self) {
+        let message = ServerMessage::Queued{waiting: self.queue.len(), needed: self.settings.min_players};
+        let ids: Vec<usize> = self.queue.iter().map(|(id, _)| *id).collect();
+        for id in ids {
+            self.send(id, &message);
+        }
+    }
+
+    // Seeded configs still give every game its own, reproducible, world
+    fn start_game(&mut self) {
+        let game_id = self.next_game_id;
+        self.next_game_id += 1;
+        let mut config = self.config.clone();
+        config.seed = config.seed.map(|seed| seed.wrapping_add(game_id as u64 - 1));
+        let mut game = GameServer::new(&config, self.settings.clone());
+
+        let players: Vec<(usize, String)> = self.queue.drain(..self.settings.min_players).collect();
+        for (id, name) in players {
+            let outbound = match self.waiting.remove(&id) {
+                Some(outbound) => outbound,
+                None => continue,
+            };
+            game.handle(Inbound::Connected(id, outbound));
+            game.handle(Inbound::Message(id, ClientMessage::Join{name}));
+            self.routes.insert(id, game_id);
+        }
+        // Started right away, so nobody leaving before the first tick holds the game up
+        game.start();
+        self.games.insert(game_id, game);
+    }
+
+    // Without an id the oldest running game is watched
+    fn watch(&mut self, id: usize, game_id: Option<usize>) -> Option<ServerMessage> {
+        let game_id = match game_id.or_else(|| self.games.keys().min().copied()) {
+            Some(game_id) if self.games.contains_key(&game_id) => game_id,
+            _ => return Some(error("No such game")),
+        };
+        let outbound = self.waiting.remove(&id)?;
+        let game = self.games.get_mut(&game_id).unwrap();
+        game.handle(Inbound::Connected(id, outbound));
+        game.handle(Inbound::Message(id, ClientMessage::Watch{game_id: Some(game_id)}));
+        self.routes.insert(id, game_id);
+        None
+    }
+
+    fn reply(&mut self, id: usize, message: ClientMessage) -> Option<ServerMessage> {
+        let queued = self.queue.iter().any(|(queued, _)| *queued == id);
+        match (message, queued) {
+            (ClientMessage::Quit, _) => {
+                if let Some(mut outbound) = self.waiting.remove(&id) {
+                    outbound.close();
+                }
+                self.leave(id);
+                self.announce_queue();
+                None
+            },
+            (ClientMessage::Join{..}, true) => Some(error("Already queued")),
+            (ClientMessage::Join{name}, false) => {
+                self.queue.push((id, name));
+                if self.queue.len() >= self.settings.min_players {
+                    self.start_game();
+                }
+                self.announce_queue();
+                None
+            },
+            (ClientMessage::Watch{game_id}, false) => self.watch(id, game_id),
+            (_, true) => Some(error("Waiting for players")),
+            (_, false) => Some(error("Join first")),
+        }
+    }
+
+    fn finish_games(&mut self) {
+        let mut finished: Vec<usize> = self.games
+            .iter()
+            .filter(|(_, game)| game.is_over())
+            .map(|(game_id, _)| *game_id)
+            .collect();
+        finished.sort_unstable();
+        for game_id in finished {
+            let game = self.games.remove(&game_id).unwrap();
+            (self.report)(&FinishedGame{
+                game_id,
+                seed: game.world().seed(),
+                ticks: game.world().tick(),
+                ranking: game.ranking(),
+            });
+            // Players are back in the lobby and may join the queue again
+            self.routes.retain(|_, routed| *routed != game_id);
+            self.waiting.extend(game.into_connections());
+        }
+    }
+}
+
+impl Session for Lobby {
+    fn handle(&mut self, inbound: Inbound) {
+        let routed = match &inbound {
+            Inbound::Connected(..) => None,
+            Inbound::Message(id, _) | Inbound::Invalid(id, _) | Inbound::Disconnected(id) => self.routes.get(id).copied(),
+        };
+        if let Some(game_id) = routed {
+            if let Inbound::Disconnected(id) | Inbound::Message(id, ClientMessage::Quit) = &inbound {
+                self.routes.remove(id);
+            }
+            if let Some(game) = self.games.get_mut(&game_id) {
+                game.handle(inbound);
+            }
+            return;
+        }
+        match inbound {
+            Inbound::Connected(id, outbound) => {
+                self.waiting.insert(id, outbound);
+            },
+            Inbound::Disconnected(id) => {
+                self.leave(id);
+                self.announce_queue();
+            },
+            Inbound::Invalid(id, message) => self.send(id, &ServerMessage::Error{message}),
+            Inbound::Message(id, message) => {
+                if let Some(reply) = self.reply(id, message) {
+                    self.send(id, &reply);
+                }
+            },
+        }
+    }
+
+    fn tick(&mut self) {
+        for game in self.games.values_mut() {
+            game.tick();
+        }
+        self.finish_games();
+    }
+
+    // Runs for as long as connections can arrive
+    fn is_over(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+    use crate::server::Outbound;
+
+    struct Sink;
+
+    impl Outbound for Sink {
+        fn send(&mut self, _message: &ServerMessage) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn ignore(_game: &FinishedGame) {}
+
+    #[test]
+    fn games_need_two_players() {
+        let mut config = GameConfig::new();
+        config.settings.verbose = false;
+        let mut settings = ServerSettings::new();
+        settings.min_players = 0;
+        let mut lobby = Lobby::new(&config, settings, ignore);
+        lobby.handle(Inbound::Connected(1, Box::new(Sink)));
+        lobby.handle(Inbound::Message(1, ClientMessage::Join{name: String::from("Ann")}));
+        assert!(lobby.games().is_empty());
+        assert_eq!(lobby.queued(), 1);
+        lobby.handle(Inbound::Connected(2, Box::new(Sink)));
+        lobby.handle(Inbound::Message(2, ClientMessage::Join{name: String::from("Bo")}));
+        assert_eq!(lobby.games().len(), 1);
+        assert_eq!(lobby.queued(), 0);
+    }
+}
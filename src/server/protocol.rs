@@ -0,0 +1,148 @@
+use serde::{Serialize, Deserialize};
+
+use crate::world::Action;
+use crate::world::board::Board;
+use crate::world::command::{parse_action, CommandError};
+use crate::world::observation::{Observation, ObservationDiff, SpectatorView};
+use crate::world::position::Coord;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    Join{name: String},
+    // The user of the action is always replaced by the joined player
+    Action{action: Action},
+    State,
+    // Lobbies pick the game to watch, a single game server ignores the id
+    Watch{#[serde(default)] game_id: Option<usize>},
+    Quit,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ranking {
+    pub player_id: u16,
+    pub game_name: String,
+    pub rank: u16,
+    pub score: u16,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Queued{waiting: usize, needed: usize},
+    Joined{player_id: u16, game_name: String},
+    Accepted,
+    Error{message: String},
+    Tick{diff: ObservationDiff},
+    State{observation: Observation},
+    GameOver{ranking: Vec<Ranking>},
+    Watching{delay: usize},
+    Spectate{view: SpectatorView},
+}
+
+fn coord(coord: &Coord) -> String {
+    format!("{},{}", coord.x, coord.y)
+}
+
+fn path_line(path: &[Coord]) -> String {
+    let mut words = vec![String::from("path")];
+    words.extend(path.iter().map(coord));
+    words.join(" ")
+}
+
+// Text commands as typed in the terminal client, plus `join NAME`, `watch [GAME]`, `state` and `quit`
+pub fn parse_line(line: &str) -> Result<ClientMessage, CommandError> {
+    let line = line.trim();
+    match line.split_once(' ') {
+        Some(("join", name)) if !name.trim().is_empty() => {
+            return Ok(ClientMessage::Join{name: String::from(name.trim())});
+        },
+        Some(("watch", game_id)) => {
+            let game_id = game_id.trim().parse().map_err(|_| CommandError::BadArgument(String::from(game_id.trim())))?;
+            return Ok(ClientMessage::Watch{game_id: Some(game_id)});
+        },
+        _ => (),
+    }
+    match line {
+        "state" => Ok(ClientMessage::State),
+        "watch" => Ok(ClientMessage::Watch{game_id: None}),
+        "quit" => Ok(ClientMessage::Quit),
+        _ => Ok(ClientMessage::Action{action: parse_action(0, line)?}),
+    }
+}
+
+// Multi line messages are closed by a single `end` line
+pub fn to_lines(message: &ServerMessage) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    match message {
+        ServerMessage::Queued{waiting, needed} => lines.push(format!("queued {} {}", waiting, needed)),
+        ServerMessage::Joined{player_id, game_name} => lines.push(format!("joined {} {}", player_id, game_name)),
+        ServerMessage::Accepted => lines.push(String::from("ok")),
+        ServerMessage::Error{message} => lines.push(format!("error {}", message)),
+        ServerMessage::Tick{diff} => {
+            lines.push(format!("tick {} {:?} {:?} {}", diff.tick, diff.state, diff.check, diff.score));
+            lines.push(format!("fog {}", diff.fog_status));
+            if let Some(flyer) = &diff.flyer {
+                lines.push(format!("flyer {}", coord(flyer)));
+            }
+            if let Some(path) = &diff.flyer_path {
+                lines.push(path_line(path));
+            }
+            for piece in diff.pieces.iter() {
+                lines.push(format!("piece {} {:?} {} {}", piece.piece_id, piece.kind, piece.player, coord(&piece.at)));
+            }
+            for piece_id in diff.removed.iter() {
+                lines.push(format!("gone {}", piece_id));
+            }
+            if !diff.fogged.is_empty() {
+                let fogged: Vec<String> = diff.fogged.iter().map(coord).collect();
+                lines.push(format!("fogged {}", fogged.join(" ")));
+            }
+            lines.push(String::from("end"));
+        },
+        ServerMessage::State{observation} => {
+            let diff = observation.diff(None);
+            lines.push(format!("state {} {:?} {:?} {}", diff.tick, diff.state, diff.check, diff.score));
+            for player in observation.players.iter() {
+                lines.push(format!("player {} {} {} {}", player.player_id, player.score, player.alive, player.game_name));
+            }
+            if let Some(path) = &diff.flyer_path {
+                lines.push(path_line(path));
+            }
+            for piece in diff.pieces.iter() {
+                lines.push(format!("piece {} {:?} {} {}", piece.piece_id, piece.kind, piece.player, coord(&piece.at)));
+            }
+            lines.push(String::from("end"));
+        },
+        ServerMessage::GameOver{ranking} => {
+            lines.push(String::from("game_over"));
+            for rank in ranking.iter() {
+                lines.push(format!("rank {} {} {} {}", rank.rank, rank.player_id, rank.score, rank.game_name));
+            }
+            lines.push(String::from("end"));
+        },
+        ServerMessage::Watching{delay} => lines.push(format!("watching {}", delay)),
+        ServerMessage::Spectate{view} => {
+            lines.push(format!("spectate {}", view.tick));
+            lines.push(format!("fog {}", view.fog_status));
+            if let Some(flyer) = &view.flyer {
+                lines.push(format!("flyer {}", coord(flyer)));
+            }
+            if !view.flyer_path.is_empty() {
+                lines.push(path_line(&view.flyer_path));
+            }
+            for player in view.players.iter() {
+                lines.push(format!("player {} {} {:?} {:?} {}", player.player_id, player.score, player.state, player.check, player.game_name));
+            }
+            for piece in view.pieces.iter() {
+                lines.push(format!("piece {} {:?} {} {}", piece.piece_id, piece.kind, piece.player, coord(&piece.at)));
+            }
+            let fogged: Vec<String> = view.fog.coords_of(1).iter().map(coord).collect();
+            if !fogged.is_empty() {
+                lines.push(format!("fogged {}", fogged.join(" ")));
+            }
+            lines.push(String::from("end"));
+        },
+    }
+    lines.iter().map(|line| format!("{}\n", line)).collect()
+}
@@ -0,0 +1,62 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::Duration;
+
+use crate::server::{Inbound, Outbound, next_connection_id};
+use crate::server::protocol::{ServerMessage, parse_line, to_lines};
+
+// A client that stops reading is dropped instead of stalling the clock for everyone
+const WRITE_TIMEOUT_MS: u64 = 100;
+
+struct LineClient {
+    stream: TcpStream,
+}
+
+impl Outbound for LineClient {
+    fn send(&mut self, message: &ServerMessage) -> io::Result<()> {
+        self.stream.write_all(to_lines(message).as_bytes())
+    }
+
+    fn close(&mut self) {
+        self.stream.shutdown(Shutdown::Both).ok();
+    }
+}
+
+fn read_lines(id: usize, stream: TcpStream, inbox: Sender<Inbound>) {
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        if line.trim().is_empty() { continue; }
+        let inbound = match parse_line(&line) {
+            Ok(message) => Inbound::Message(id, message),
+            Err(err) => Inbound::Invalid(id, err.to_string()),
+        };
+        if inbox.send(inbound).is_err() { return; }
+    }
+    inbox.send(Inbound::Disconnected(id)).ok();
+}
+
+// Accepts line protocol clients in the background, every line is forwarded to the inbox
+pub fn listen(listener: TcpListener, inbox: Sender<Inbound>) {
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(_) => continue,
+            };
+            if stream.set_write_timeout(Some(Duration::from_millis(WRITE_TIMEOUT_MS))).is_err() { continue; }
+            let reader = match stream.try_clone() {
+                Ok(reader) => reader,
+                Err(_) => continue,
+            };
+            let id = next_connection_id();
+            if inbox.send(Inbound::Connected(id, Box::new(LineClient{stream}))).is_err() { return; }
+            let inbox = inbox.clone();
+            thread::spawn(move || read_lines(id, reader, inbox));
+        }
+    });
+}
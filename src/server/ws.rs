@@ -0,0 +1,109 @@
+use std::io;
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::Duration;
+
+use tungstenite::{accept_with_config, Error, Message, WebSocket};
+use tungstenite::protocol::WebSocketConfig;
+
+use crate::server::{Inbound, Outbound, next_connection_id};
+use crate::server::protocol::{ClientMessage, ServerMessage};
+
+// The socket never blocks, so the lock is only held for a single read or write attempt
+const POLL_MS: u64 = 5;
+// Clients that fall this far behind on reading are dropped
+const MAX_WRITE_BUFFER: usize = 1 << 20;
+
+type Socket = Arc<Mutex<WebSocket<TcpStream>>>;
+
+struct JsonClient {
+    socket: Socket,
+}
+
+fn would_block(err: &Error) -> bool {
+    matches!(err, Error::Io(err) if err.kind() == io::ErrorKind::WouldBlock)
+}
+
+fn to_io(err: Error) -> io::Error {
+    match err {
+        Error::Io(err) => err,
+        err => io::Error::other(err),
+    }
+}
+
+impl Outbound for JsonClient {
+    fn send(&mut self, message: &ServerMessage) -> io::Result<()> {
+        let json = serde_json::to_string(message)?;
+        match self.socket.lock().unwrap().send(Message::Text(json)) {
+            // Still queued, the reader thread flushes it
+            Err(err) if would_block(&err) => Ok(()),
+            result => result.map_err(to_io),
+        }
+    }
+
+    fn close(&mut self) {
+        let mut socket = self.socket.lock().unwrap();
+        socket.close(None).ok();
+        socket.flush().ok();
+    }
+}
+
+fn read_messages(id: usize, socket: Socket, inbox: Sender<Inbound>) {
+    loop {
+        let read = {
+            let mut socket = socket.lock().unwrap();
+            match socket.flush() {
+                Err(err) if !would_block(&err) => break,
+                _ => socket.read(),
+            }
+        };
+        let inbound = match read {
+            Ok(Message::Text(text)) => match serde_json::from_str::<ClientMessage>(&text) {
+                Ok(message) => Inbound::Message(id, message),
+                Err(err) => Inbound::Invalid(id, err.to_string()),
+            },
+            Ok(Message::Close(_)) => break,
+            Ok(_) => continue,
+            Err(ref err) if would_block(err) => {
+                thread::sleep(Duration::from_millis(POLL_MS));
+                continue;
+            },
+            Err(_) => break,
+        };
+        if inbox.send(inbound).is_err() { return; }
+    }
+    inbox.send(Inbound::Disconnected(id)).ok();
+}
+
+fn handshake(stream: TcpStream) -> Option<Socket> {
+    let config = WebSocketConfig{max_write_buffer_size: MAX_WRITE_BUFFER, ..WebSocketConfig::default()};
+    let socket = accept_with_config(stream, Some(config)).ok()?;
+    socket.get_ref().set_nonblocking(true).ok()?;
+    Some(Arc::new(Mutex::new(socket)))
+}
+
+// Accepts WebSocket clients speaking the JSON form of the protocol messages
+pub fn listen(listener: TcpListener, inbox: Sender<Inbound>) {
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(_) => continue,
+            };
+            let inbox = inbox.clone();
+            // The handshake blocks, so it is done off the accept thread
+            thread::spawn(move || {
+                let socket = match handshake(stream) {
+                    Some(socket) => socket,
+                    None => return,
+                };
+                let id = next_connection_id();
+                let client = JsonClient{socket: socket.clone()};
+                if inbox.send(Inbound::Connected(id, Box::new(client))).is_err() { return; }
+                read_messages(id, socket, inbox);
+            });
+        }
+    });
+}
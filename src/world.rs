@@ -1,16 +1,24 @@
 use std::collections::HashMap;
 
+use rand::{RngCore, SeedableRng};
+use rand::rngs::StdRng;
+use serde::{Serialize, Deserialize};
+
 use crate::world::position::{Coord, Offset, Positional};
-use crate::world::builders::{add_zones_rects, add_fog, add_fly_path};
+use crate::world::builders::{add_fog, add_fly_path, add_loot, Loot, ZoneShape};
 use crate::world::board::Board;
-use crate::world::pieces::{Piece, PieceType};
-use crate::world::player::{Player, PlayerState, GamerNamer};
-use crate::world::fog::Fog;
+use crate::world::direction::Direction;
+use crate::world::pieces::{Piece, PieceType, PieceValues, NEUTRAL_PLAYER};
+use crate::world::player::{Player, PlayerState, CheckState, GamerNamer};
+use crate::world::fog::{Fog, FogState};
 use crate::world::flyer::Flyer;
-use crate::world::historian::Historian;
+use crate::world::historian::{Historian, Event};
+use crate::world::snapshot::ordered;
 
 pub mod board;
 pub mod builders;
+pub mod command;
+pub mod config;
 pub mod display;
 pub mod position;
 pub mod pieces;
@@ -19,57 +27,149 @@ pub mod player;
 pub mod fog;
 pub mod flyer;
 pub mod historian;
+pub mod observation;
+pub mod replay;
+pub mod snapshot;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub enum Action {
     None(u16),
     Drop(u16),
     Fly(u16, Offset),
     Move(u16, Coord, Coord),
+    Promote(u16, Coord, PieceType),
+}
+
+impl Action {
+    pub fn user(&self) -> u16 {
+        match self {
+            Action::None(user) => *user,
+            Action::Drop(user) => *user,
+            Action::Fly(user, _off) => *user,
+            Action::Move(user, _from, _to) => *user,
+            Action::Promote(user, _coord, _kind) => *user,
+        }
+    }
+
+    pub fn with_user(self, user: u16) -> Action {
+        match self {
+            Action::None(_) => Action::None(user),
+            Action::Drop(_) => Action::Drop(user),
+            Action::Fly(_, off) => Action::Fly(user, off),
+            Action::Move(_, from, to) => Action::Move(user, from, to),
+            Action::Promote(_, coord, kind) => Action::Promote(user, coord, kind),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub enum MoveRejection {
+    NotAMove,
+    OutsideBoard,
+    NotBoarded,
+    NoPiece,
+    NotOwner,
+    Illegal,
+    LeavesKingChecked,
+    CannotPromote,
+    Contested,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum ActionError {
+    UnknownPlayer,
+    WrongState,
+    CannotDropYet,
+    IllegalFly,
+    IllegalMove(MoveRejection),
+}
+
+// Piece, from and to
+pub type Placement = (u16, Coord, Coord);
+
+struct BoardMove {
+    user: u16,
+    kind: PieceType,
+    placements: Vec<Placement>, // Castling also moves the rook
 }
 
-struct WorldSettings {
-    drop_height: u16,
-    zone_every: usize,
-    zone_rest: usize,
-    flyer_every: usize,
-    fly_start: i16,
-    verbose: bool,
+impl BoardMove {
+    fn contests(&self, other: &BoardMove) -> bool {
+        self.placements.iter().any(|(_, from, to)| other.placements
+            .iter()
+            .any(|(_, other_from, other_to)| to == other_to || (to == other_from && from == other_to)))
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WorldSettings {
+    pub drop_height: u16,
+    pub promotion_distance: i16,
+    pub loot_density: f32,
+    pub fog_takes_pieces: bool,
+    pub fog_king_ticks: usize, // Zero means kings are not harmed by fog
+    pub zone_every: usize,
+    pub zone_rest: usize,
+    pub flyer_every: usize,
+    pub fly_start: i16,
+    pub move_range_limit: i16,
+    pub zone_shape: ZoneShape,
+    pub piece_values: PieceValues,
+    pub vision_radius: usize,
+    pub verbose: bool,
 }
 
 impl WorldSettings {
-    fn new() -> WorldSettings {
+    pub fn new() -> WorldSettings {
         WorldSettings{
             fly_start: -5,
             drop_height: 10,
+            promotion_distance: 6,
+            loot_density: 0.05,
+            fog_takes_pieces: true,
+            fog_king_ticks: 3,
             zone_every: 10,
             zone_rest: 42,
             flyer_every: 1,
+            move_range_limit: 9,
+            zone_shape: ZoneShape::Rects,
+            piece_values: PieceValues::new(),
+            vision_radius: 4,
             verbose: true,
         }
     }
 }
 
+impl Default for WorldSettings {
+    fn default() -> Self {
+        WorldSettings::new()
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct World {
     settings: WorldSettings,
     pub fog: Fog,
+    #[serde(serialize_with = "ordered")]
     pub pieces: HashMap<u16, Piece>,
     pub pieces_map: Vec<Vec<u16>>,
     pub flyer: Flyer,
+    #[serde(serialize_with = "ordered")]
     pub players: HashMap<u16, Player>,
 
     req_air_action: Vec<Action>,
     req_board_action: Vec<Action>,
     alive: bool,
     tick: usize,
+    seed: u64,
     historian: Historian,
 }
 
 impl World {
-    fn new(shape: Coord) -> Self {
+    fn new(shape: Coord, seed: u64, settings: WorldSettings) -> Self {
         let fog = Fog::new(shape);
         let pieces = fog.zones.new_with(0);
-        let settings = WorldSettings::new();
         World {
             fog,
             flyer: Flyer::new(settings.fly_start),
@@ -81,44 +181,51 @@ impl World {
             req_air_action: Vec::new(),
             req_board_action: Vec::new(),
             tick: 0,
+            seed,
             alive: true,
         }
     }
 
-    pub fn request_action(&mut self, action: Action) {
+    pub fn request_action(&mut self, action: Action) -> Result<(), ActionError> {
+        let state = match self.players.get(&action.user()) {
+            Some(player) => player.state,
+            None => return Err(ActionError::UnknownPlayer),
+        };
         match action {
-            Action::Drop(user) => {
-
-                if self.players.contains_key(&user) && self.players[&user].state.is_flying() && self.flyer.can_drop() {
-                    self.req_air_action.push(action);
-                }
+            Action::Drop(_user) => {
+                if !state.is_flying() { return Err(ActionError::WrongState); }
+                if !self.flyer.can_drop() { return Err(ActionError::CannotDropYet); }
+                self.req_air_action.push(action);
             }
-            Action::Fly(user, _off) => {
-                if self.players.contains_key(&user) && self.players[&user].state.can_fly() {
-                    self.req_air_action.push(action);
+            Action::Fly(user, off) => {
+                if !state.can_fly() { return Err(ActionError::WrongState); }
+                if let PlayerState::Falling(_h, coord) = state {
+                    if off.chebyshev() > 1 || !coord.translate(off).is_inside(&self.fog.shape()) {
+                        return Err(ActionError::IllegalFly);
+                    }
                 }
+                // One step per tick, a later request replaces the earlier one
+                self.req_air_action.retain(|a| !(a.user() == user && matches!(a, Action::Fly(..))));
+                self.req_air_action.push(action);
             }
-            Action::Move(user, _from, _to) => {
-                if self.players.contains_key(&user) && self.players[&user].state.is_boarded() {
-                    self.req_board_action.retain(|a| match a {
-                        Action::Move(uid, _from, _to) => return *uid != user,
-                        _ => return true
-                    });
-                }
+            Action::Move(user, from, to) => {
+                if !state.is_boarded() { return Err(ActionError::WrongState); }
+                self.validate_board_move(user, from, to).map_err(ActionError::IllegalMove)?;
+                self.req_board_action.retain(|a| a.user() != user);
+                self.req_board_action.push(action);
+            }
+            Action::Promote(user, coord, kind) => {
+                if !state.is_boarded() { return Err(ActionError::WrongState); }
+                self.validate_promotion(user, coord, kind).map_err(ActionError::IllegalMove)?;
+                self.req_board_action.retain(|a| a.user() != user);
                 self.req_board_action.push(action);
             }
             Action::None(user) => {
-                self.req_air_action.retain(|a | match a {
-                    Action::Drop(uid) => return *uid == user,
-                    Action::Fly(uid, _off) => return *uid == user,
-                    _ => return true,
-                });
-                self.req_board_action.retain(|a | match a {
-                    Action::Move(uid, _from, _to) => return *uid == user,
-                    _ => return true,
-                });
+                self.req_air_action.retain(|a| a.user() != user);
+                self.req_board_action.retain(|a| a.user() != user);
             }
         }
+        Ok(())
     }
 
     pub fn no_piece_between(&self, from: &Coord, to: &Coord) -> bool {
@@ -135,97 +242,416 @@ impl World {
         }
     }
 
-    pub fn do_board_move(&mut self, action: Action) {
+    pub fn do_board_move(&mut self, action: Action) -> Result<(), MoveRejection> {
+        self.do_board_moves(vec![action]).pop().unwrap().1
+    }
+
+    pub fn do_board_moves(&mut self, mut actions: Vec<Action>) -> Vec<(Action, Result<(), MoveRejection>)> {
+        // Every player's move is judged on the board as it was before any of them
+        actions.sort_by_key(|action| action.user());
+        let mut results = Vec::new();
+        let mut moves: Vec<(Action, BoardMove)> = Vec::new();
+        let mut promotions = Vec::new();
+        for action in actions {
+            match action {
+                Action::Promote(..) => promotions.push(action),
+                Action::Move(user, from, to) => match self.validate_board_move(user, from, to) {
+                    Ok(board_move) => moves.push((action, board_move)),
+                    Err(rejection) => {
+                        let result = self.do_reject(action, rejection);
+                        results.push((action, result));
+                    },
+                },
+                _ => results.push((action, Err(MoveRejection::NotAMove))),
+            }
+        }
+        for action in promotions {
+            let result = self.do_promote(action);
+            results.push((action, result));
+        }
+
+        // Pieces claiming the same square or swapping squares all stay put
+        let contested: Vec<bool> = moves
+            .iter()
+            .enumerate()
+            .map(|(idx, (_, board_move))| moves
+                .iter()
+                .enumerate()
+                .any(|(other_idx, (_, other))| idx != other_idx && board_move.contests(other)))
+            .collect();
+        let mut accepted = Vec::new();
+        for ((action, board_move), contested) in moves.into_iter().zip(contested) {
+            if contested {
+                let result = self.do_reject(action, MoveRejection::Contested);
+                results.push((action, result));
+            } else {
+                results.push((action, Ok(())));
+                accepted.push(board_move);
+            }
+        }
+        self.do_apply_moves(accepted);
+        results
+    }
+
+    fn do_reject(&mut self, action: Action, rejection: MoveRejection) -> Result<(), MoveRejection> {
+        if let Action::Move(user, from, to) = action {
+            self.historian.record_player(
+                user,
+                self.tick,
+                PieceType::Empty,
+                Event::Rejected{from, to, rejection},
+            );
+        }
+        Err(rejection)
+    }
+
+    fn validate_board_move(&self, user: u16, from: Coord, to: Coord) -> Result<BoardMove, MoveRejection> {
+        let shape = self.fog.shape();
+        if !from.is_inside(&shape) || !to.is_inside(&shape) {
+            return Err(MoveRejection::OutsideBoard);
+        }
+        if !self.players.get(&user).is_some_and(|p| p.state.is_boarded()) {
+            return Err(MoveRejection::NotBoarded);
+        }
+        let piece_id = self.pieces_map[from.y][from.x];
+        let kind = match self.pieces.get(&piece_id) {
+            Some(piece) => {
+                if piece.player != user || !piece.alive { return Err(MoveRejection::NotOwner); }
+                if (to - from).chebyshev() == 0 || !piece.can_move_to(self, &to) {
+                    return Err(MoveRejection::Illegal);
+                }
+                piece.kind
+            },
+            None => return Err(MoveRejection::NoPiece),
+        };
+        let placements = self.placements(piece_id, from, to);
+        if self.leaves_king_checked(user, &placements) {
+            return Err(MoveRejection::LeavesKingChecked);
+        }
+        Ok(BoardMove{user, kind, placements})
+    }
+
+    fn placements(&self, piece_id: u16, from: Coord, to: Coord) -> Vec<Placement> {
+        let mut placements = vec![(piece_id, from, to)];
+        // Castling, the rook is the first piece beyond the king and jumps over it
+        let off: Offset = to - from;
+        if self.pieces[&piece_id].kind.is_king() && off.chebyshev() == 2 {
+            let dir = off.as_direction().unwrap();
+            if let Some(rook_id) = self.pieces_map.find_first(&from, dir) {
+                let rook_from = *self.pieces[&rook_id].position().unwrap();
+                placements.push((rook_id, rook_from, from.translate_direction(dir)));
+            }
+        }
+        placements
+    }
+
+    fn do_apply_moves(&mut self, moves: Vec<BoardMove>) {
+        // Lift all moving pieces first, pieces moving away escape being taken
+        for board_move in moves.iter() {
+            for (piece_id, from, _to) in board_move.placements.iter() {
+                if self.pieces_map[from.y][from.x] == *piece_id {
+                    self.pieces_map[from.y][from.x] = 0;
+                }
+            }
+        }
+
+        for board_move in moves.iter() {
+            for (idx, (piece_id, from, to)) in board_move.placements.iter().enumerate() {
+                match self.pieces.get_mut(piece_id) {
+                    Some(piece) if piece.alive => piece.place(*to),
+                    _ => continue,
+                }
+                // Taking (preparing, actual happens below)
+                let other_piece_id = self.pieces_map[to.y][to.x];
+                self.pieces_map[to.y][to.x] = *piece_id;
+                let (kind, event) = if idx == 0 {
+                    (board_move.kind, Event::Move{piece_id: *piece_id, from: *from, to: *to})
+                } else {
+                    (PieceType::Rook, Event::Castle{piece_id: *piece_id, from: *from, to: *to})
+                };
+                self.historian.record_player(board_move.user, self.tick, kind, event);
+                self.do_take(other_piece_id, board_move.user, kind);
+            }
+        }
+
+        for board_move in moves.iter() {
+            for (piece_id, _from, _to) in board_move.placements.iter() {
+                self.do_recruit(*piece_id);
+            }
+        }
+    }
+
+    pub fn do_promote(&mut self, action: Action) -> Result<(), MoveRejection> {
         match action {
-            Action::Move(user, from, to) => {
-                let piece_id = self.pieces_map[from.y][from.x];
-                if piece_id == 0 { return; }
-                let mut other_piece_id = 0 as u16;
-                let mut kind = PieceType::Empty;
-                match self.pieces.get_mut(&piece_id) {
-                    Some(piece) => {
-                        if piece.player != user || !piece.alive { return; }
-                        match piece.kind.intermediat_steps(from, to) {
-                            None => (),
-                            Some(steps) => {
-                                for step in steps {
-                                    if self.pieces_map[step.y][step.x] > 0 {
-                                        return ;
-                                    }
-                                }
-
-                                // Taking (preparing, actual happens below)
-                                other_piece_id = self.pieces_map[to.y][to.x];
-                                kind = piece.kind.clone();
-
-                                // Moving
-                                piece.place(to.clone());
-                                self.pieces_map[to.y][to.x] = piece_id;
-                                self.pieces_map[from.y][from.x] = 0;
-                                self.historian.record_player(
-                                    user,
-                                    self.tick,
-                                    piece.kind,
-                                    format!("Move {:?} -> {:?}", from, to),
-                                )
-                            }
-                        }
-                    }
-                    _ => (),
+            Action::Promote(user, coord, kind) => {
+                let result = self.resolve_promotion(user, coord, kind);
+                if let Err(rejection) = result {
+                    self.historian.record_player(
+                        user,
+                        self.tick,
+                        PieceType::Pawn,
+                        Event::RejectedPromotion{at: coord, kind, rejection},
+                    );
                 }
-                self.do_take(other_piece_id, user, kind);
+                result
             },
-            _ => ()
+            _ => Err(MoveRejection::NotAMove),
         }
     }
 
-    fn do_take(&mut self, other_piece_id: u16, taker_id: u16, taken_by: PieceType) {
-        match self.pieces.get_mut(&other_piece_id) {
-            Some(other) => {
-                if !other.alive { return; }
-                let pos = other.position().unwrap().clone();
-                other.alive = false;
-                self.players.get_mut(&taker_id).unwrap().score += other.kind.value();
-                match other.kind {
-                    PieceType::Empty => (),
-                    PieceType::King => {
-                        if self.players.contains_key(&other.player) {
-                            let rank = self.players.iter()
-                                .filter(| (_, p) | p.state.is_alive()).count();
-                            if rank == 2 {
-                                // Give rank 1 to self
-                                self.players.get_mut(&taker_id).unwrap()
-                                    .transition(PlayerState::Dead(1));
-                                self.alive = false;
-                            }
-                            self.players.get_mut(&other.player).unwrap()
-                                .transition(PlayerState::Dead(rank as u16));
-                        }
-                        self.historian.record_player(
-                            taker_id,
-                            self.tick,
-                            taken_by,
-                            format!("Capture {:?} @ {:?}", other.kind, pos),
-                        );
-                    },
-                    _ => {
-                        self.historian.record_player(
-                            taker_id,
-                            self.tick,
-                            taken_by,
-                            format!("Capture {:?} @ {:?}", other.kind, pos),
-                        )
-                    }
+    fn validate_promotion(&self, user: u16, coord: Coord, kind: PieceType) -> Result<u16, MoveRejection> {
+        if !coord.is_inside(&self.fog.shape()) {
+            return Err(MoveRejection::OutsideBoard);
+        }
+        if !self.players.get(&user).is_some_and(|p| p.state.is_boarded()) {
+            return Err(MoveRejection::NotBoarded);
+        }
+        let piece_id = self.pieces_map[coord.y][coord.x];
+        match self.pieces.get(&piece_id) {
+            Some(piece) => {
+                if piece.player != user || !piece.alive { return Err(MoveRejection::NotOwner); }
+                if !kind.is_promotion() || !piece.can_promote(self.settings.promotion_distance) {
+                    return Err(MoveRejection::CannotPromote);
                 }
+                Ok(piece_id)
             },
-            _ => (),
+            None => Err(MoveRejection::NoPiece),
+        }
+    }
+
+    fn resolve_promotion(&mut self, user: u16, coord: Coord, kind: PieceType) -> Result<(), MoveRejection> {
+        let piece_id = self.validate_promotion(user, coord, kind)?;
+        self.pieces.get_mut(&piece_id).unwrap().kind = kind;
+        self.historian.record_player(
+            user,
+            self.tick,
+            PieceType::Pawn,
+            Event::Promote{piece_id, at: coord, kind},
+        );
+        Ok(())
+    }
+
+    fn do_recruit(&mut self, piece_id: u16) {
+        let (user, pos) = match self.pieces.get(&piece_id) {
+            Some(piece) if piece.alive && !piece.is_neutral() => (piece.player, *piece.position().unwrap()),
+            _ => return,
+        };
+        let shape = self.fog.shape();
+        for dir in Direction::iterator() {
+            let coord = pos.translate_direction(dir);
+            if !coord.is_inside(&shape) { continue; }
+            let other_id = self.pieces_map[coord.y][coord.x];
+            if let Some(other) = self.pieces.get_mut(&other_id) {
+                if !other.is_neutral() { continue; }
+                other.player = user;
+                self.historian.record_player(
+                    user,
+                    self.tick,
+                    other.kind,
+                    Event::Recruit{piece_id: other_id, at: coord},
+                );
+            }
+        }
+    }
+
+    // The piece on a square once the placements are made
+    fn piece_at_after(&self, coord: &Coord, placements: &[Placement]) -> u16 {
+        if let Some((piece_id, _, _)) = placements.iter().find(|(_, _, to)| to == coord) {
+            return *piece_id;
+        }
+        if placements.iter().any(|(_, from, _)| from == coord) { return 0; }
+        self.pieces_map[coord.y][coord.x]
+    }
+
+    fn leaves_king_checked(&self, user: u16, placements: &[Placement]) -> bool {
+        let king_id = self.players[&user].king_id;
+        let king = &self.pieces[&king_id];
+        let king_pos = match placements.iter().find(|(piece_id, _, _)| *piece_id == king_id) {
+            Some((_, _, to)) => *to,
+            None => *king.position().unwrap(),
+        };
+        !king.others_threatening_me_after(self, &king_pos, placements).is_empty()
+    }
+
+    pub fn piece_moves(&self, piece_id: u16) -> Vec<Coord> {
+        let (user, from, mut coords) = match self.pieces.get(&piece_id) {
+            Some(piece) if piece.alive => (piece.player, *piece.position().unwrap(), piece.moves(self)),
+            _ => return Vec::new(),
+        };
+        coords.retain(|to| !self.leaves_king_checked(user, &self.placements(piece_id, from, *to)));
+        coords
+    }
+
+    fn player_piece_ids(&self, user: u16) -> Vec<u16> {
+        let mut ids: Vec<u16> = self.pieces
+            .iter()
+            .filter(|(_, piece)| piece.player == user && piece.alive)
+            .map(|(id, _)| *id)
+            .collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    pub fn legal_moves(&self, user: u16) -> Vec<(Coord, Coord)> {
+        let mut moves = Vec::new();
+        if !self.players.get(&user).is_some_and(|p| p.state.is_boarded()) {
+            return moves;
+        }
+        for piece_id in self.player_piece_ids(user) {
+            let from = *self.pieces[&piece_id].position().unwrap();
+            for to in self.piece_moves(piece_id) {
+                moves.push((from, to));
+            }
+        }
+        moves
+    }
+
+    pub fn check_state(&self, user: u16) -> CheckState {
+        if !self.players.get(&user).is_some_and(|p| p.state.is_boarded()) {
+            return CheckState::Safe;
+        }
+        let king_id = self.players[&user].king_id;
+        let checked = self.pieces[&king_id].my_king_is_checked(self);
+        let can_move = self.player_piece_ids(user)
+            .into_iter()
+            .any(|piece_id| !self.piece_moves(piece_id).is_empty());
+        match (checked, can_move) {
+            (true, true) => CheckState::Check,
+            (true, false) => CheckState::Checkmate,
+            (false, true) => CheckState::Safe,
+            (false, false) => CheckState::Stalemate,
+        }
+    }
+
+    fn do_resolve_checks(&mut self) {
+        let mut uids: Vec<u16> = self.players.keys().copied().collect();
+        uids.sort_unstable();
+        for uid in uids {
+            if !self.alive { return; }
+            if !self.players[&uid].state.is_boarded() { continue; }
+            let check = self.check_state(uid);
+            if self.players[&uid].check == check { continue; }
+            self.players.get_mut(&uid).unwrap().check = check;
+            self.historian.record_player(
+                uid,
+                self.tick,
+                PieceType::King,
+                Event::Check(check),
+            );
+            if check == CheckState::Checkmate {
+                self.do_eliminate(uid);
+            }
+        }
+    }
+
+    fn do_eliminate(&mut self, user: u16) {
+        if !self.players.get(&user).is_some_and(|p| p.state.is_alive()) { return; }
+        let rank = self.players.values().filter(|p| p.state.is_alive()).count();
+        self.players.get_mut(&user).unwrap().transition(PlayerState::Dead(rank as u16));
+        self.historian.record_player(user, self.tick, PieceType::King, Event::Eliminated{rank: rank as u16});
+
+        // The king leaves the board, the rest of the army is left as loot
+        let king_id = self.players[&user].king_id;
+        self.do_remove(king_id);
+        for piece in self.pieces.values_mut().filter(|p| p.player == user) {
+            piece.player = NEUTRAL_PLAYER;
+        }
+        if rank <= 2 {
+            // Give rank 1 to the last one standing, if any, the game is over either way
+            for (uid, player) in self.players.iter_mut().filter(|(_, p)| p.state.is_alive()) {
+                player.transition(PlayerState::Dead(1));
+                self.historian.record_world(self.tick, Event::Winner{player: *uid});
+            }
+            self.alive = false;
+        }
+    }
+
+    fn do_remove(&mut self, piece_id: u16) {
+        if let Some(piece) = self.pieces.get_mut(&piece_id) {
+            if let Some(pos) = piece.position() {
+                if self.pieces_map[pos.y][pos.x] == piece_id {
+                    self.pieces_map[pos.y][pos.x] = 0;
+                }
+            }
+            piece.alive = false;
+        }
+    }
+
+    fn in_fog(&self, coord: &Coord) -> bool {
+        self.fog.fog[coord.y][coord.x] > 0
+    }
+
+    fn do_fog_damage(&mut self) {
+        if self.settings.fog_takes_pieces {
+            let mut piece_ids: Vec<u16> = self.pieces.keys().copied().collect();
+            piece_ids.sort_unstable();
+            for piece_id in piece_ids {
+                let piece = &self.pieces[&piece_id];
+                if !piece.alive || piece.kind.is_king() { continue; }
+                let pos = *piece.position().unwrap();
+                if !self.in_fog(&pos) { continue; }
+                let (user, kind) = (piece.player, piece.kind);
+                self.do_remove(piece_id);
+                if user != NEUTRAL_PLAYER {
+                    self.historian.record_player(
+                        user,
+                        self.tick,
+                        kind,
+                        Event::LostInFog{piece_id, at: pos},
+                    );
+                }
+            }
         }
 
+        if self.settings.fog_king_ticks == 0 { return; }
+        let mut uids: Vec<u16> = self.players.keys().copied().collect();
+        uids.sort_unstable();
+        for uid in uids {
+            if !self.alive { return; }
+            if !self.players[&uid].state.is_boarded() { continue; }
+            let king_id = self.players[&uid].king_id;
+            let pos = *self.pieces[&king_id].position().unwrap();
+            let in_fog = self.in_fog(&pos);
+            let player = self.players.get_mut(&uid).unwrap();
+            player.fog_ticks = if in_fog { player.fog_ticks + 1 } else { 0 };
+            if player.fog_ticks >= self.settings.fog_king_ticks {
+                self.historian.record_player(
+                    uid,
+                    self.tick,
+                    PieceType::King,
+                    Event::LostInFog{piece_id: king_id, at: pos},
+                );
+                self.do_eliminate(uid);
+            }
+        }
+    }
+
+    fn do_take(&mut self, other_piece_id: u16, taker_id: u16, taken_by: PieceType) {
+        let (kind, owner, pos) = match self.pieces.get_mut(&other_piece_id) {
+            Some(other) if other.alive => {
+                let pos = *other.position().unwrap();
+                other.alive = false;
+                (other.kind, other.player, pos)
+            },
+            _ => return,
+        };
+        self.players.get_mut(&taker_id).unwrap().score += self.settings.piece_values.of(kind);
+        if kind.is_king() {
+            self.do_eliminate(owner);
+        }
+        self.historian.record_player(
+            taker_id,
+            self.tick,
+            taken_by,
+            Event::Capture{piece_id: other_piece_id, kind, at: pos},
+        );
     }
 
     fn do_move_falling(&mut self, mut fly_actions: Vec<Action>) {
         let shape = self.fog.shape();
-        while let Some(action) = fly_actions.pop() {
+        // Players in order, request_action keeps at most one step each
+        fly_actions.sort_by_key(|action| action.user());
+        for action in fly_actions {
             match action {
                 Action::Fly(user, off) => {
                     if !self.players.contains_key(&user) || !self.players[&user].state.can_fly() { continue; }
@@ -238,7 +664,7 @@ impl World {
                                     user,
                                     self.tick,
                                     PieceType::King,
-                                    format!("Fly -> {:?}", next_coord),
+                                    Event::Fly{from: coord, to: next_coord},
                                 );
                             }
                         },
@@ -250,12 +676,53 @@ impl World {
         }
     }
 
+    fn do_push_apart(&mut self) {
+        let shape = self.fog.shape();
+        let mut uids: Vec<u16> = self.players.keys().copied().collect();
+        uids.sort_unstable();
+        // Lower player ids keep their position, others are pushed to the closest free spot
+        let mut taken: Vec<(u16, Coord)> = Vec::new();
+        for uid in uids {
+            let (h, coord) = match self.players[&uid].state {
+                PlayerState::Falling(h, coord) => (h, coord),
+                _ => continue,
+            };
+            let is_taken = |other: &Coord| taken
+                .iter()
+                .any(|(th, tc)| *th == h && tc.x == other.x && tc.y == other.y);
+            let mut pushed = coord;
+            let mut reach = 1;
+            while is_taken(&pushed) && reach < shape.x.max(shape.y) as i16 {
+                if let Some(other) = Direction::iterator()
+                    .map(|dir| coord.translate_n_direction(dir, reach))
+                    .find(|other| other.is_inside(&shape) && !is_taken(other))
+                {
+                    pushed = other;
+                }
+                reach += 1;
+            }
+            taken.push((h, pushed));
+            if pushed.x == coord.x && pushed.y == coord.y { continue; }
+            self.players.get_mut(&uid).unwrap().transition(PlayerState::Falling(h, pushed));
+            self.historian.record_player(
+                uid,
+                self.tick,
+                PieceType::King,
+                Event::Push{from: coord, to: pushed, height: h},
+            );
+        }
+    }
+
     fn do_drop(&mut self, mut drop_actions: Vec<Action>) {
         if self.flyer.must_drop() {
             // Force Drop
             match self.flyer.coord() {
                 Some(flyer) => {
-                    let uids: Vec<u16> = self.players.iter().map(|(k, _)| k.clone()).collect();
+                    let mut uids: Vec<u16> = self.players.keys().copied().collect();
+                    uids.sort_unstable();
+                    if uids.iter().any(|uid| self.players[uid].state.is_flying()) {
+                        self.historian.record_world(self.tick, Event::ForcedDrop{at: flyer});
+                    }
                     for uid in uids {
                         if !self.players.contains_key(&uid) || !self.players[&uid].state.is_flying() { continue; }
                         self.players.get_mut(&uid).unwrap().transition(PlayerState::Falling(self.settings.drop_height, flyer.clone()));
@@ -263,7 +730,7 @@ impl World {
                             uid,
                             self.tick,
                             PieceType::King,
-                            format!("Drop -> {:?}:{}", flyer, self.settings.drop_height),
+                            Event::Drop{at: flyer, height: self.settings.drop_height},
                         );
                     }
                 }
@@ -282,7 +749,7 @@ impl World {
                                     user,
                                     self.tick,
                                     PieceType::King,
-                                    format!("Drop -> {:?}:{}", flyer, self.settings.drop_height),
+                                    Event::Drop{at: flyer, height: self.settings.drop_height},
                                 );
                             }
                             _ => (),
@@ -295,8 +762,39 @@ impl World {
         }
     }
 
+    fn landing_spot(&self, user: u16, coord: Coord) -> Coord {
+        // Nearest free square that is neither fogged nor threatened, settling for less if none
+        let shape = self.fog.shape();
+        let king = Piece::new(PieceType::King, user);
+        let mut unfogged = None;
+        let mut free = None;
+        for reach in 0..(shape.x.max(shape.y) as i16) {
+            let mut ring: Vec<Coord> = Vec::new();
+            for dy in -reach..(reach + 1) {
+                for dx in -reach..(reach + 1) {
+                    let off = Offset{x: dx, y: dy};
+                    if off.chebyshev() != reach { continue; }
+                    let other = coord.translate(off);
+                    if other.is_inside(&shape) && self.pieces_map[other.y][other.x] == 0 {
+                        ring.push(other);
+                    }
+                }
+            }
+            ring.sort_by_key(|other| ((*other - coord).sq_len(), other.y, other.x));
+            for other in ring {
+                if free.is_none() { free = Some(other); }
+                if self.in_fog(&other) { continue; }
+                if unfogged.is_none() { unfogged = Some(other); }
+                if king.others_threatening_me_at(self, &other).is_empty() { return other; }
+            }
+        }
+        unfogged.or(free).unwrap_or(coord)
+    }
+
     fn do_lower_falling(&mut self) {
-        let uids: Vec<u16> = self.players.iter().map(|(k, _)| k.clone()).collect();
+        // Sorted so that simultaneous landings resolve the same way every time
+        let mut uids: Vec<u16> = self.players.keys().copied().collect();
+        uids.sort_unstable();
         for uid in uids.iter() {
             match self.players[uid].state {
                 PlayerState::Falling(h, coord) => {
@@ -306,29 +804,33 @@ impl World {
                             *uid,
                             self.tick,
                             PieceType::King,
-                            format!("Fall -> {:?}:{}", coord, h - 1),
+                            Event::Fall{at: coord, height: h - 1},
                         )
                     } else {
                         self.players.get_mut(uid).unwrap().transition(PlayerState::Boarded);
+                        let landing = self.landing_spot(*uid, coord);
+                        if landing.x != coord.x || landing.y != coord.y {
+                            self.historian.record_player(
+                                *uid,
+                                self.tick,
+                                PieceType::King,
+                                Event::Slide{from: coord, to: landing},
+                            );
+                        }
+                        let coord = landing;
                         let mut piece = Piece::new(PieceType::King, *uid);
                         piece.place(coord.clone());
                         let piece_id = self.pieces.len() as u16 + 1;
                         self.pieces.insert(piece_id, piece);
                         self.players.get_mut(uid).unwrap().king_id = piece_id;
-                        if self.pieces_map[coord.y][coord.x] > 0 {
-                            self.do_take(
-                                self.pieces_map[coord.y][coord.x],
-                                *uid,
-                                PieceType::King,
-                            )
-                        }
                         self.pieces_map[coord.y][coord.x] = piece_id;
                         self.historian.record_player(
                             *uid,
                             self.tick,
                             PieceType::King,
-                            format!("Land -> {:?}", coord),
+                            Event::Land{piece_id, at: coord},
                         );
+                        self.do_recruit(piece_id);
                     }
                 },
                 _ => (),
@@ -336,15 +838,49 @@ impl World {
         }
     }
 
+    fn do_fly(&mut self) {
+        self.flyer.tick();
+        if let Some(at) = self.flyer.coord() {
+            self.historian.record_world(self.tick, Event::Flyer{at});
+        }
+    }
+
+    fn do_contract(&mut self) {
+        let zone = self.fog.active_zone();
+        let was_done = self.fog.is_done();
+        match self.fog.contract(self.settings.zone_rest) {
+            FogState::Resting => (),
+            FogState::Contracting => {
+                if self.fog.active_zone() != zone {
+                    self.historian.record_world(self.tick, Event::ZoneActivated{zone: self.fog.active_zone()});
+                }
+                self.historian.record_world(
+                    self.tick,
+                    Event::ZoneShrink{zone: self.fog.active_zone(), step: self.fog.fog_step()},
+                );
+            },
+            FogState::Zone => self.historian.record_world(self.tick, Event::ZoneClosed{zone}),
+            // The fog keeps reporting done, only tell once
+            FogState::Done => if !was_done { self.historian.record_world(self.tick, Event::FogDone) },
+        }
+    }
+
     pub fn do_tick(&mut self) {
         if !self.alive { return; }
         // Modify world
         if self.flyer.flying() {
-            if (self.tick % self.settings.flyer_every) == 0 { self.flyer.tick(); }
+            if self.tick.is_multiple_of(self.settings.flyer_every) { self.do_fly(); }
         } else {
-            if (self.tick % self.settings.zone_every) == 0 { self.fog.contract(self.settings.zone_rest); }
+            if self.tick.is_multiple_of(self.settings.zone_every) { self.do_contract(); }
         }
 
+        let accepted: Vec<Action> = self.req_air_action
+            .iter()
+            .chain(self.req_board_action.iter())
+            .copied()
+            .collect();
+        self.historian.record_actions(self.tick, accepted);
+
         // Copy concurrent actions
         let drop_actions: Vec<Action> = self.req_air_action
             .iter()
@@ -360,23 +896,43 @@ impl World {
         self.req_air_action.clear();
 
         self.do_move_falling(fly_actions);
+        self.do_push_apart();
         self.do_lower_falling();
         self.do_drop(drop_actions);
 
-        let action = self.req_board_action.pop();
-        match action {
-            Some(action) => self.do_board_move(action),
-            _ => ()
-        }
+        let board_actions: Vec<Action> = self.req_board_action.drain(..).collect();
+        self.do_board_moves(board_actions);
+        self.do_fog_damage();
+        self.do_resolve_checks();
         self.tick += 1;
     }
 
+    pub fn historian(&self) -> &Historian {
+        &self.historian
+    }
+
+    pub fn settings(&self) -> &WorldSettings {
+        &self.settings
+    }
+
+    pub fn tick(&self) -> usize {
+        self.tick
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.alive
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
     pub fn players_by_score(&self) -> Vec<Player> {
         let mut players: Vec<Player> = self.players
             .iter()
             .map(|(_, p)| p.clone())
             .collect();
-        players.sort_by(|a, b| b.score.cmp(&a.score));
+        players.sort_by(|a, b| b.score.cmp(&a.score).then(a.player_id.cmp(&b.player_id)));
         players
     }
 
@@ -403,19 +959,400 @@ impl World {
         self.players.iter().filter(| (_, p) | p.state.is_airborne()).count()
     }
 
-    pub fn add_player(&mut self, user_name: String, gamer_namer: &mut GamerNamer) {
-        let uid = self.players.len() as u16 + 1;
+    pub fn init_loot(
+        &mut self,
+        looter: fn(&mut Loot, Coord, u16, &mut dyn RngCore),
+        rng: &mut dyn RngCore,
+    ) {
+        let shape = self.fog.shape();
+        let count = ((shape.x * shape.y) as f32 * self.settings.loot_density) as u16;
+        let mut loot = Vec::new();
+        looter(&mut loot, shape, count, rng);
+        for (kind, coord) in loot {
+            if self.pieces_map[coord.y][coord.x] > 0 { continue; }
+            let mut piece = Piece::new(kind, NEUTRAL_PLAYER);
+            piece.place(coord);
+            let piece_id = self.pieces.len() as u16 + 1;
+            self.pieces.insert(piece_id, piece);
+            self.pieces_map[coord.y][coord.x] = piece_id;
+        }
+    }
+
+    pub fn add_player(&mut self, user_name: String, gamer_namer: &mut GamerNamer) -> u16 {
+        let uid = self.players.keys().max().map_or(1, |uid| uid + 1);
         self.players.insert(uid, Player::new(uid, user_name, gamer_namer));
+        uid
+    }
+
+    // Only for players still up in the air, e.g. leaving before the game starts
+    pub fn remove_player(&mut self, user: u16) -> bool {
+        if !self.players.get(&user).is_some_and(|p| p.state.is_flying()) { return false; }
+        self.players.remove(&user);
+        self.req_air_action.retain(|a| a.user() != user);
+        self.req_board_action.retain(|a| a.user() != user);
+        true
     }
 }
 
-pub fn spawn(shape: Coord, nzones: u16, players: &Vec<String>) -> World {
-    let mut world = World::new(shape);
-    world.fog.init(nzones, add_zones_rects, add_fog);
-    world.flyer.init(world.fog.shape(), add_fly_path);
-    let mut namer = GamerNamer::new();
+pub fn spawn(shape: Coord, nzones: u16, players: &[String], seed: u64) -> World {
+    spawn_with_settings(WorldSettings::new(), shape, nzones, players, seed)
+}
+
+pub fn spawn_with_settings(
+    settings: WorldSettings,
+    shape: Coord,
+    nzones: u16,
+    players: &[String],
+    seed: u64,
+) -> World {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut world = World::new(shape, seed, settings);
+    world.fog.init(nzones, world.settings.zone_shape.builder(), add_fog, &mut rng);
+    world.flyer.init(world.fog.shape(), add_fly_path, &mut rng);
+    world.init_loot(add_loot, &mut rng);
+    let mut namer = GamerNamer::new(&mut rng);
     for player in players.iter() {
         world.add_player( player.clone(), &mut namer);
     }
     world
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn board() -> World {
+        board_with_kings(Coord{x: 0, y: 11}, Coord{x: 11, y: 0})
+    }
+
+    fn board_with_kings(ann_at: Coord, bo_at: Coord) -> World {
+        let mut settings = WorldSettings::new();
+        settings.verbose = false;
+        let mut world = World::new(Coord{x: 12, y: 12}, 1, settings);
+        let mut namer = GamerNamer::new(&mut StdRng::seed_from_u64(1));
+        for (name, king_at) in [("Ann", ann_at), ("Bo", bo_at)] {
+            let uid = world.add_player(String::from(name), &mut namer);
+            world.players.get_mut(&uid).unwrap().transition(PlayerState::Boarded);
+            let king_id = put(&mut world, PieceType::King, uid, king_at);
+            world.players.get_mut(&uid).unwrap().king_id = king_id;
+        }
+        world
+    }
+
+    fn put(world: &mut World, kind: PieceType, player: u16, at: Coord) -> u16 {
+        let mut piece = Piece::new(kind, player);
+        piece.place(at);
+        let piece_id = world.pieces.len() as u16 + 1;
+        world.pieces.insert(piece_id, piece);
+        world.pieces_map[at.y][at.x] = piece_id;
+        piece_id
+    }
+
+    fn at(world: &World, piece_id: u16) -> Coord {
+        *world.pieces[&piece_id].position().unwrap()
+    }
+
+    fn falling(world: &mut World, name: &str, height: u16, at: Coord) -> u16 {
+        let mut namer = GamerNamer::new(&mut StdRng::seed_from_u64(world.players.len() as u64));
+        let uid = world.add_player(String::from(name), &mut namer);
+        world.players.get_mut(&uid).unwrap().transition(PlayerState::Falling(height, at));
+        uid
+    }
+
+    #[test]
+    fn moving_next_to_loot_recruits_it() {
+        let mut world = board();
+        put(&mut world, PieceType::Rook, 1, Coord{x: 2, y: 5});
+        let loot = put(&mut world, PieceType::Knight, NEUTRAL_PLAYER, Coord{x: 7, y: 6});
+        let far = put(&mut world, PieceType::Pawn, NEUTRAL_PLAYER, Coord{x: 9, y: 9});
+        world.do_board_move(Action::Move(1, Coord{x: 2, y: 5}, Coord{x: 6, y: 5})).unwrap();
+        assert_eq!(world.pieces[&loot].player, 1);
+        assert!(world.pieces[&far].is_neutral());
+    }
+
+    #[test]
+    fn fog_takes_pieces_and_then_kings() {
+        let mut world = board();
+        let rook = put(&mut world, PieceType::Rook, 1, Coord{x: 2, y: 5});
+        world.fog.fog[5][2] = 1;
+        world.fog.fog[11][0] = 1;
+        world.do_fog_damage();
+        assert!(!world.pieces[&rook].alive);
+        assert_eq!(world.pieces_map[5][2], 0);
+        for _ in 1..world.settings.fog_king_ticks {
+            assert!(world.players[&1].state.is_boarded());
+            world.do_fog_damage();
+        }
+        assert!(matches!(world.players[&1].state, PlayerState::Dead(2)));
+        assert!(!world.is_alive());
+    }
+
+    #[test]
+    fn fog_done_is_told_once() {
+        let mut world = board();
+        for _ in 0..3 {
+            world.do_contract();
+        }
+        assert!(world.fog.is_done());
+        let told = world.historian.world_events().iter().filter(|r| matches!(r.event, Event::FogDone)).count();
+        assert_eq!(told, 1);
+    }
+
+    #[test]
+    fn landing_slides_off_taken_and_threatened_squares() {
+        let mut world = board();
+        put(&mut world, PieceType::Pawn, NEUTRAL_PLAYER, Coord{x: 5, y: 5});
+        put(&mut world, PieceType::Rook, 2, Coord{x: 11, y: 4});
+        let cy = falling(&mut world, "Cy", 1, Coord{x: 5, y: 5});
+        world.do_lower_falling();
+        assert!(world.players[&cy].state.is_boarded());
+        assert_eq!(at(&world, world.players[&cy].king_id), Coord{x: 4, y: 5});
+    }
+
+    #[test]
+    fn falling_players_are_pushed_apart() {
+        let mut world = board();
+        let cy = falling(&mut world, "Cy", 4, Coord{x: 5, y: 5});
+        let dee = falling(&mut world, "Dee", 4, Coord{x: 5, y: 5});
+        let eve = falling(&mut world, "Eve", 3, Coord{x: 5, y: 5});
+        world.do_push_apart();
+        let coord = |uid: u16| match world.players[&uid].state {
+            PlayerState::Falling(_, coord) => coord,
+            _ => panic!("not falling"),
+        };
+        assert_eq!(coord(cy), Coord{x: 5, y: 5});
+        assert_eq!((coord(dee) - Coord{x: 5, y: 5}).chebyshev(), 1);
+        // Only players at the same height collide
+        assert_eq!(coord(eve), Coord{x: 5, y: 5});
+    }
+
+    #[test]
+    fn cannot_take_own_piece() {
+        let mut world = board();
+        put(&mut world, PieceType::Rook, 1, Coord{x: 2, y: 5});
+        put(&mut world, PieceType::Knight, 1, Coord{x: 2, y: 8});
+        let result = world.validate_board_move(1, Coord{x: 2, y: 5}, Coord{x: 2, y: 8});
+        assert!(matches!(result, Err(MoveRejection::Illegal)));
+    }
+
+    #[test]
+    fn pawn_cannot_turn_back() {
+        let mut world = board();
+        let pawn = put(&mut world, PieceType::Pawn, 1, Coord{x: 5, y: 8});
+        world.do_board_move(Action::Move(1, Coord{x: 5, y: 8}, Coord{x: 5, y: 7})).unwrap();
+        assert_eq!(at(&world, pawn), Coord{x: 5, y: 7});
+        let result = world.validate_board_move(1, Coord{x: 5, y: 7}, Coord{x: 5, y: 8});
+        assert!(matches!(result, Err(MoveRejection::Illegal)));
+    }
+
+    #[test]
+    fn castling_needs_a_rook() {
+        let mut world = board_with_kings(Coord{x: 2, y: 5}, Coord{x: 11, y: 0});
+        put(&mut world, PieceType::Knight, 1, Coord{x: 6, y: 5});
+        let result = world.validate_board_move(1, Coord{x: 2, y: 5}, Coord{x: 4, y: 5});
+        assert!(matches!(result, Err(MoveRejection::Illegal)));
+    }
+
+    #[test]
+    fn castling_moves_the_rook() {
+        let mut world = board_with_kings(Coord{x: 2, y: 5}, Coord{x: 11, y: 0});
+        let king = world.players[&1].king_id;
+        let rook = put(&mut world, PieceType::Rook, 1, Coord{x: 6, y: 5});
+        world.do_board_move(Action::Move(1, Coord{x: 2, y: 5}, Coord{x: 4, y: 5})).unwrap();
+        assert_eq!(at(&world, king), Coord{x: 4, y: 5});
+        assert_eq!(at(&world, rook), Coord{x: 3, y: 5});
+        assert_eq!(world.pieces_map[5][6], 0);
+    }
+
+    #[test]
+    fn castling_cannot_uncover_check() {
+        // The rook leaves the line to the enemy rook as it jumps over the king
+        let mut world = board_with_kings(Coord{x: 2, y: 5}, Coord{x: 11, y: 0});
+        put(&mut world, PieceType::Rook, 1, Coord{x: 6, y: 5});
+        put(&mut world, PieceType::Rook, 2, Coord{x: 10, y: 5});
+        let result = world.validate_board_move(1, Coord{x: 2, y: 5}, Coord{x: 4, y: 5});
+        assert!(matches!(result, Err(MoveRejection::LeavesKingChecked)));
+        assert!(!world.legal_moves(1).contains(&(Coord{x: 2, y: 5}, Coord{x: 4, y: 5})));
+    }
+
+    #[test]
+    fn cannot_move_into_check() {
+        let mut world = board();
+        put(&mut world, PieceType::Knight, 1, Coord{x: 0, y: 8});
+        put(&mut world, PieceType::Rook, 2, Coord{x: 0, y: 4});
+        put(&mut world, PieceType::Rook, 2, Coord{x: 1, y: 4});
+        let pinned = world.validate_board_move(1, Coord{x: 0, y: 8}, Coord{x: 1, y: 6});
+        assert!(matches!(pinned, Err(MoveRejection::LeavesKingChecked)));
+        let stepped = world.validate_board_move(1, Coord{x: 0, y: 11}, Coord{x: 1, y: 10});
+        assert!(matches!(stepped, Err(MoveRejection::Illegal)));
+    }
+
+    #[test]
+    fn check_states() {
+        let mut world = board();
+        assert_eq!(world.check_state(1), CheckState::Safe);
+        let rook = put(&mut world, PieceType::Rook, 2, Coord{x: 0, y: 5});
+        assert_eq!(world.check_state(1), CheckState::Check);
+        put(&mut world, PieceType::Rook, 2, Coord{x: 1, y: 5});
+        assert_eq!(world.check_state(1), CheckState::Checkmate);
+        world.pieces_map[5][0] = 0;
+        world.pieces.get_mut(&rook).unwrap().place(Coord{x: 5, y: 10});
+        world.pieces_map[10][5] = rook;
+        assert_eq!(world.check_state(1), CheckState::Stalemate);
+    }
+
+    #[test]
+    fn knights_check_at_short_range_limits() {
+        let mut world = board();
+        world.settings.move_range_limit = 2;
+        put(&mut world, PieceType::Knight, 2, Coord{x: 2, y: 10});
+        assert_eq!(world.check_state(1), CheckState::Check);
+    }
+
+    #[test]
+    fn checkmate_eliminates_with_rank() {
+        let mut world = board();
+        let mut namer = GamerNamer::new(&mut StdRng::seed_from_u64(2));
+        world.add_player(String::from("Cy"), &mut namer);
+        let knight = put(&mut world, PieceType::Knight, 1, Coord{x: 5, y: 8});
+        put(&mut world, PieceType::Rook, 2, Coord{x: 0, y: 5});
+        put(&mut world, PieceType::Rook, 2, Coord{x: 1, y: 5});
+        world.do_resolve_checks();
+        assert_eq!(world.players[&1].check, CheckState::Checkmate);
+        assert!(matches!(world.players[&1].state, PlayerState::Dead(3)));
+        assert!(world.is_alive());
+        assert!(world.pieces[&knight].alive && world.pieces[&knight].is_neutral());
+    }
+
+    #[test]
+    fn last_checkmate_ends_world() {
+        let mut world = board();
+        put(&mut world, PieceType::Rook, 2, Coord{x: 0, y: 5});
+        put(&mut world, PieceType::Rook, 2, Coord{x: 1, y: 5});
+        world.do_resolve_checks();
+        assert!(matches!(world.players[&1].state, PlayerState::Dead(2)));
+        assert!(matches!(world.players[&2].state, PlayerState::Dead(1)));
+        assert!(!world.is_alive());
+    }
+
+    #[test]
+    fn observed_moves_hide_unseen_pieces() {
+        let mut world = board();
+        let rook = put(&mut world, PieceType::Rook, 1, Coord{x: 2, y: 5});
+        put(&mut world, PieceType::Knight, 1, Coord{x: 1, y: 11});
+        put(&mut world, PieceType::Pawn, 1, Coord{x: 3, y: 10});
+        put(&mut world, PieceType::Pawn, 2, Coord{x: 9, y: 5});
+        put(&mut world, PieceType::Rook, 2, Coord{x: 8, y: 11});
+
+        // Blocked by a pawn out of sight, only found out on the tick
+        let blocked = Action::Move(1, Coord{x: 2, y: 5}, Coord{x: 10, y: 5});
+        assert!(matches!(world.request_action(blocked), Err(ActionError::IllegalMove(MoveRejection::Illegal))));
+        assert!(world.request_observed_action(blocked).is_ok());
+        let results = world.do_board_moves(world.req_board_action.clone());
+        assert!(matches!(results[0].1, Err(MoveRejection::Illegal)));
+        assert_eq!(at(&world, rook), Coord{x: 2, y: 5});
+
+        // Pinned by a rook out of sight
+        let pinned = Action::Move(1, Coord{x: 1, y: 11}, Coord{x: 2, y: 9});
+        assert!(matches!(world.request_action(pinned), Err(ActionError::IllegalMove(MoveRejection::LeavesKingChecked))));
+        assert!(world.request_observed_action(pinned).is_ok());
+
+        // What the player can see is still rejected
+        let own = Action::Move(1, Coord{x: 1, y: 11}, Coord{x: 3, y: 10});
+        assert!(matches!(world.request_observed_action(own), Err(ActionError::IllegalMove(MoveRejection::Illegal))));
+    }
+
+    #[test]
+    fn same_square_bounces_both() {
+        let mut world = board();
+        let ann = put(&mut world, PieceType::Rook, 1, Coord{x: 2, y: 5});
+        let bo = put(&mut world, PieceType::Rook, 2, Coord{x: 5, y: 2});
+        let results = world.do_board_moves(vec![
+            Action::Move(1, Coord{x: 2, y: 5}, Coord{x: 5, y: 5}),
+            Action::Move(2, Coord{x: 5, y: 2}, Coord{x: 5, y: 5}),
+        ]);
+        assert!(results.iter().all(|(_, result)| matches!(result, Err(MoveRejection::Contested))));
+        assert_eq!(at(&world, ann), Coord{x: 2, y: 5});
+        assert_eq!(at(&world, bo), Coord{x: 5, y: 2});
+    }
+
+    #[test]
+    fn swap_bounces_both() {
+        let mut world = board();
+        let ann = put(&mut world, PieceType::Rook, 1, Coord{x: 2, y: 5});
+        let bo = put(&mut world, PieceType::Rook, 2, Coord{x: 5, y: 5});
+        let results = world.do_board_moves(vec![
+            Action::Move(1, Coord{x: 2, y: 5}, Coord{x: 5, y: 5}),
+            Action::Move(2, Coord{x: 5, y: 5}, Coord{x: 2, y: 5}),
+        ]);
+        assert!(results.iter().all(|(_, result)| matches!(result, Err(MoveRejection::Contested))));
+        assert_eq!(at(&world, ann), Coord{x: 2, y: 5});
+        assert_eq!(at(&world, bo), Coord{x: 5, y: 5});
+        assert!(world.pieces[&ann].alive && world.pieces[&bo].alive);
+    }
+
+    #[test]
+    fn moving_away_escapes_capture() {
+        let mut world = board();
+        let ann = put(&mut world, PieceType::Rook, 1, Coord{x: 2, y: 5});
+        let bo = put(&mut world, PieceType::Rook, 2, Coord{x: 5, y: 5});
+        let results = world.do_board_moves(vec![
+            Action::Move(1, Coord{x: 2, y: 5}, Coord{x: 5, y: 5}),
+            Action::Move(2, Coord{x: 5, y: 5}, Coord{x: 5, y: 9}),
+        ]);
+        assert!(results.iter().all(|(_, result)| result.is_ok()));
+        assert_eq!(at(&world, ann), Coord{x: 5, y: 5});
+        assert_eq!(at(&world, bo), Coord{x: 5, y: 9});
+        assert!(world.pieces[&bo].alive);
+        assert_eq!(world.pieces_map[5][5], ann);
+        assert_eq!(world.pieces_map[9][5], bo);
+    }
+
+    #[test]
+    fn repeated_fly_replaces_earlier() {
+        let mut world = board();
+        world.players.get_mut(&1).unwrap().transition(PlayerState::Falling(5, Coord{x: 6, y: 6}));
+        for _ in 0..6 {
+            world.request_action(Action::Fly(1, Offset{x: -1, y: 0})).unwrap();
+        }
+        world.request_action(Action::Fly(1, Offset{x: 0, y: 1})).unwrap();
+        world.do_move_falling(world.req_air_action.clone());
+        assert!(matches!(world.players[&1].state, PlayerState::Falling(5, Coord{x: 6, y: 7})));
+    }
+
+    #[test]
+    fn last_player_eliminated_ends_world() {
+        let mut settings = WorldSettings::new();
+        settings.verbose = false;
+        let mut world = spawn_with_settings(settings, Coord{x: 20, y: 12}, 3, &[String::from("Solo")], 1);
+        world.request_action(Action::Drop(1)).ok();
+        for _ in 0..3000 {
+            if !world.is_alive() { break; }
+            world.do_tick();
+        }
+        assert!(!world.is_alive());
+        assert!(matches!(world.players[&1].state, PlayerState::Dead(1)));
+    }
+
+    #[test]
+    fn promotion_waits_for_other_moves() {
+        let mut world = board();
+        let pawn = put(&mut world, PieceType::Pawn, 1, Coord{x: 5, y: 0});
+        world.pieces_map[0][5] = 0;
+        world.pieces.get_mut(&pawn).unwrap().place(Coord{x: 5, y: 6});
+        world.pieces_map[6][5] = pawn;
+        let bo_king = world.players[&2].king_id;
+        world.pieces_map[0][11] = 0;
+        world.pieces.get_mut(&bo_king).unwrap().place(Coord{x: 8, y: 7});
+        world.pieces_map[7][8] = bo_king;
+
+        // The king step is judged against the pawn, not the rook it becomes
+        let results = world.do_board_moves(vec![
+            Action::Promote(1, Coord{x: 5, y: 6}, PieceType::Rook),
+            Action::Move(2, Coord{x: 8, y: 7}, Coord{x: 7, y: 6}),
+        ]);
+        assert!(results.iter().all(|(_, result)| result.is_ok()));
+        assert_eq!(world.pieces[&pawn].kind, PieceType::Rook);
+        assert_eq!(at(&world, bo_king), Coord{x: 7, y: 6});
+    }
+}
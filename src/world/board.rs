@@ -1,4 +1,4 @@
-use rand::Rng;
+use rand::{Rng, RngCore};
 use std::cmp;
 
 use crate::world::position::{Coord, Positional};
@@ -9,7 +9,7 @@ pub trait Board {
     fn new_with<T: Clone>(&self, value: T) -> Vec<Vec<T>>;
     fn new_when(&self, other: &Self, other_value: u16, fill: u16) -> Self;
     fn fill(&mut self, c1: &Coord, c2: &Coord, when: u16, value: u16);
-    fn mark_rnd_position(&mut self, value: u16) -> Coord;
+    fn mark_rnd_position(&mut self, value: u16, rng: &mut dyn RngCore) -> Coord;
     fn max_val(&self) -> u16;
     fn max_when(&self, other: &Self, other_value: u16) -> u16;
     fn coords_of(&self, value: u16) -> Vec<Coord>;
@@ -66,9 +66,8 @@ impl Board for Vec<Vec<u16>> {
         }
     }
 
-    fn mark_rnd_position(&mut self, value: u16) -> Coord {
+    fn mark_rnd_position(&mut self, value: u16, rng: &mut dyn RngCore) -> Coord {
         let s = self.shape();
-        let mut rng = rand::thread_rng();
         let source_x = rng.gen_range(0, s.x);
         let source_y = rng.gen_range(0, s.y);
         self[source_y][source_x] = value;
@@ -215,15 +214,15 @@ impl Board for Vec<Vec<u16>> {
     }
 
     fn find_first(&self, start: &Coord, direction: Direction) -> Option<u16> {
-        let mut pos = start.translate_direction(direction);
         let shape = self.shape();
-        while self[pos.y][pos.x] == 0 {
-            pos = pos.translate_direction(direction);
-            if !pos.is_inside(&shape) {
-                return None
+        let mut pos = start.translate_direction(direction);
+        while pos.is_inside(&shape) {
+            if self[pos.y][pos.x] != 0 {
+                return Some(self[pos.y][pos.x])
             }
+            pos = pos.translate_direction(direction);
         }
-        Some(self[pos.y][pos.x])
+        None
     }
 
     fn find_all(&self, lower_left: &Coord, upper_right: &Coord) -> Vec<u16> {
@@ -1,9 +1,51 @@
-use rand::Rng;
+use rand::{Rng, RngCore};
+use rand::seq::SliceRandom;
+use serde::{Serialize, Deserialize};
 use std::convert::TryInto;
+use std::str::FromStr;
 
 use crate::world::position::{Coord, Positional};
 use crate::world::direction::Direction;
 use crate::world::board::Board;
+use crate::world::pieces::PieceType;
+
+pub type Loot = Vec<(PieceType, Coord)>;
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ZoneShape {
+    Rects,
+    Growth,
+}
+
+impl ZoneShape {
+    pub fn builder(&self) -> fn(&mut Vec<Vec<u16>>, u16, &mut dyn RngCore) {
+        match self {
+            ZoneShape::Rects => add_zones_rects,
+            ZoneShape::Growth => add_zones_growth,
+        }
+    }
+}
+
+impl FromStr for ZoneShape {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "rects" => Ok(ZoneShape::Rects),
+            "growth" => Ok(ZoneShape::Growth),
+            _ => Err(format!("Unknown zone shape {}", name)),
+        }
+    }
+}
+
+// A chess set without the king
+const LOOT: &[PieceType] = &[
+    PieceType::Pawn, PieceType::Pawn, PieceType::Pawn, PieceType::Pawn,
+    PieceType::Pawn, PieceType::Pawn, PieceType::Pawn, PieceType::Pawn,
+    PieceType::Knight, PieceType::Knight, PieceType::Bishop, PieceType::Bishop,
+    PieceType::Rook, PieceType::Rook, PieceType::Queen,
+];
 
 fn get_zone_sizes(zones: u16, shape: &Coord, portion: f32) -> Vec<u16> {
     let mut area: u16 = (shape.x * shape.y).try_into().unwrap();
@@ -16,18 +58,17 @@ fn get_zone_sizes(zones: u16, shape: &Coord, portion: f32) -> Vec<u16> {
     return areas
 }
 
-pub fn add_zones_rects(board: &mut Vec<Vec<u16>>, zones: u16) {
+pub fn add_zones_rects(board: &mut Vec<Vec<u16>>, zones: u16, rng: &mut dyn RngCore) {
     if zones < 2 {
         return;
     }
     let shape = board.shape();
     let areas = get_zone_sizes(zones, &shape, 0.75);
-    let mut c1 = board.mark_rnd_position(1);
+    let mut c1 = board.mark_rnd_position(1, rng);
     let mut c2 = c1
         .translate_direction(Direction::East)
         .translate_direction(Direction::South);
 
-    let mut rng = rand::thread_rng();
     for zone in 1..zones {
         while areas[zone as usize] > c1.area(&c2) as u16 {
             let grow = rng.gen_range(0, 4);
@@ -44,6 +85,37 @@ pub fn add_zones_rects(board: &mut Vec<Vec<u16>>, zones: u16) {
     board.fill(&Coord{x: 0, y: 0}, &shape, 0, zones + 1);
 }
 
+pub fn add_zones_growth(board: &mut Vec<Vec<u16>>, zones: u16, rng: &mut dyn RngCore) {
+    if zones < 2 {
+        return;
+    }
+    let shape = board.shape();
+    let areas = get_zone_sizes(zones, &shape, 0.75);
+    let origin = board.mark_rnd_position(1, rng);
+    let mut grown: u16 = 1;
+    let mut frontier: Vec<Coord> = vec![origin];
+
+    for zone in 1..zones {
+        while areas[zone as usize] > grown && !frontier.is_empty() {
+            // Grow into a random free neighbour of the blob
+            let idx = rng.gen_range(0, frontier.len());
+            let coord = frontier.swap_remove(idx);
+            if board[coord.y][coord.x] == 0 {
+                board[coord.y][coord.x] = zone + 1;
+                grown += 1;
+            }
+            for dir in Direction::cardinals() {
+                if !coord.is_legal_direction(dir) { continue; }
+                let other = coord.translate_direction(dir);
+                if other.is_inside(&shape) && board[other.y][other.x] == 0 {
+                    frontier.push(other);
+                }
+            }
+        }
+    }
+    board.fill(&Coord{x: 0, y: 0}, &shape, 0, zones + 1);
+}
+
 pub fn add_fog(fog: &mut Vec<Vec<u16>>, zones: &Vec<Vec<u16>>) {
     let mut prev_zone: Vec<Coord> = zones.coords_of(1);
     fog.apply(&prev_zone, 1);
@@ -68,20 +140,27 @@ pub fn add_fog(fog: &mut Vec<Vec<u16>>, zones: &Vec<Vec<u16>>) {
             if this_zone.len() == 0 {
                 break;
             }
+            let mut reached = false;
             for coord in this_zone.iter() {
+                // Cells next to an older, lower distance are picked up as well
                 let nmin = fog.neighbour_min(coord, &edge);
-                if nmin == cur_value {
+                if nmin > 0 && nmin <= cur_value {
                     fog[coord.y][coord.x] = cur_value + 1;
+                    reached = true;
                 }
             }
+            if !reached {
+                // Cells neighbour_min cannot see a distance from, e.g. along the top and left edges
+                fog.apply(&this_zone, cur_value + 1);
+                break;
+            }
             cur_value += 1;
         }
     }
 }
 
-fn fly_path_origin(shape: &Coord) -> (Coord, Direction) {
-    let mut rng = rand::thread_rng();
-    let dir = Direction::rnd();
+fn fly_path_origin(shape: &Coord, rng: &mut dyn RngCore) -> (Coord, Direction) {
+    let dir = Direction::rnd(rng);
     let coord = match dir {
         Direction::NorthWest => Coord{x: rng.gen_range(shape.x / 2, shape.x), y: shape.y - 1},
         Direction::North => Coord{x: rng.gen_range(shape.x / 4, shape.x * 3 / 4), y: shape.y - 1},
@@ -95,13 +174,13 @@ fn fly_path_origin(shape: &Coord) -> (Coord, Direction) {
     (coord, dir)
 }
 
-pub fn add_fly_path(path: &mut Vec<Coord>, shape: Coord) {
-    let (orig, orig_dir) = fly_path_origin(&shape);
+pub fn add_fly_path(path: &mut Vec<Coord>, shape: Coord, rng: &mut dyn RngCore) {
+    let (orig, orig_dir) = fly_path_origin(&shape, rng);
     path.push(orig);
     loop {
         let mut dir = orig_dir.clone();
         if path.len() > 3 {
-            dir = orig_dir.rnd_next(3);
+            dir = orig_dir.rnd_next(3, rng);
         }
         match path.last() {
             Some(pos) => {
@@ -114,3 +193,49 @@ pub fn add_fly_path(path: &mut Vec<Coord>, shape: Coord) {
         }
     }
 }
+
+pub fn add_loot(loot: &mut Loot, shape: Coord, count: u16, rng: &mut dyn RngCore) {
+    let board: Vec<Vec<u16>> = vec![vec![0; shape.x]; shape.y];
+    let mut coords = board.coords_of(0);
+    coords.shuffle(rng);
+    for coord in coords.into_iter().take(count as usize) {
+        loot.push((*LOOT.choose(rng).unwrap(), coord));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    use super::*;
+    use crate::world::fog::{Fog, FogState};
+
+    fn contract_all(shape: Coord, nzones: u16, zoner: fn(&mut Vec<Vec<u16>>, u16, &mut dyn RngCore), seed: u64) {
+        let mut fog = Fog::new(shape);
+        fog.init(nzones, zoner, add_fog, &mut StdRng::seed_from_u64(seed));
+        assert!(fog.fog_curve.coords_of(0).is_empty(), "seed {}: cells without fog distance", seed);
+        let mut steps = 0;
+        while !matches!(fog.contract(0), FogState::Done) {
+            steps += 1;
+            assert!(steps < shape.x * shape.y, "seed {}: fog never done", seed);
+        }
+    }
+
+    #[test]
+    fn rect_zones_contract_to_done() {
+        for seed in 0..300 {
+            contract_all(Coord{x: 20, y: 12}, 3, add_zones_rects, seed);
+            contract_all(Coord{x: 42, y: 16}, 4, add_zones_rects, seed);
+        }
+    }
+
+    #[test]
+    fn growth_zones_contract_to_done() {
+        for seed in 0..300 {
+            contract_all(Coord{x: 20, y: 12}, 3, add_zones_growth, seed);
+            contract_all(Coord{x: 42, y: 16}, 4, add_zones_growth, seed);
+            contract_all(Coord{x: 12, y: 8}, 5, add_zones_growth, seed);
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use std::fmt;
+
+use crate::world::Action;
+use crate::world::pieces::PieceType;
+use crate::world::position::{Coord, Offset};
+
+pub const COMMANDS: &str = "drop | fly DX DY | move X,Y X,Y | promote X,Y KIND | clear";
+
+#[derive(Debug)]
+pub enum CommandError {
+    Empty,
+    Unknown(String),
+    BadArgument(String),
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandError::Empty => write!(f, "No command, expected {}", COMMANDS),
+            CommandError::Unknown(cmd) => write!(f, "Unknown command {}, expected {}", cmd, COMMANDS),
+            CommandError::BadArgument(arg) => write!(f, "Bad argument {}", arg),
+        }
+    }
+}
+
+fn parse_num(arg: &str) -> Result<i16, CommandError> {
+    arg.parse().map_err(|_| CommandError::BadArgument(String::from(arg)))
+}
+
+fn parse_coord(arg: &str) -> Result<Coord, CommandError> {
+    let bad = || CommandError::BadArgument(String::from(arg));
+    let (x, y) = arg.split_once(',').ok_or_else(bad)?;
+    Ok(Coord{x: x.parse().map_err(|_| bad())?, y: y.parse().map_err(|_| bad())?})
+}
+
+fn parse_kind(arg: &str) -> Result<PieceType, CommandError> {
+    match arg.to_lowercase().as_str() {
+        "q" | "queen" => Ok(PieceType::Queen),
+        "r" | "rook" => Ok(PieceType::Rook),
+        "b" | "bishop" => Ok(PieceType::Bishop),
+        "n" | "knight" => Ok(PieceType::Knight),
+        _ => Err(CommandError::BadArgument(String::from(arg))),
+    }
+}
+
+// Reads a single player command such as `fly -1 0` or `move 3,4 3,3`
+pub fn parse_action(user: u16, line: &str) -> Result<Action, CommandError> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    match words.as_slice() {
+        ["drop"] => Ok(Action::Drop(user)),
+        ["fly", x, y] => Ok(Action::Fly(user, Offset{x: parse_num(x)?, y: parse_num(y)?})),
+        ["move", from, to] => Ok(Action::Move(user, parse_coord(from)?, parse_coord(to)?)),
+        ["promote", at, kind] => Ok(Action::Promote(user, parse_coord(at)?, parse_kind(kind)?)),
+        ["clear"] => Ok(Action::None(user)),
+        [] => Err(CommandError::Empty),
+        [cmd, ..] => Err(CommandError::Unknown(String::from(*cmd))),
+    }
+}
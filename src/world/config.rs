@@ -0,0 +1,165 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::{Serialize, Deserialize};
+
+use crate::world::{World, WorldSettings, spawn_with_settings};
+use crate::world::builders::ZoneShape;
+use crate::world::position::Coord;
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameConfig {
+    pub width: usize,
+    pub height: usize,
+    pub zones: u16,
+    pub players: Vec<String>,
+    pub seed: Option<u64>,
+    pub settings: WorldSettings,
+}
+
+impl GameConfig {
+    pub fn new() -> GameConfig {
+        GameConfig{
+            width: 42,
+            height: 16,
+            zones: 4,
+            players: vec![String::from("Player 1"), String::from("Player 2")],
+            seed: None,
+            settings: WorldSettings::new(),
+        }
+    }
+
+    pub fn from_json(json: &str) -> Result<GameConfig, ConfigError> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub fn from_toml(toml: &str) -> Result<GameConfig, ConfigError> {
+        Ok(toml::from_str(toml)?)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<GameConfig, ConfigError> {
+        let content = fs::read_to_string(&path)?;
+        let config = match path.as_ref().extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => GameConfig::from_toml(&content)?,
+            _ => GameConfig::from_json(&content)?,
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    // Catches setups that would panic or never finish building the world
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |message: String| Err(ConfigError::Invalid(message));
+        if self.width < 4 || self.height < 4 || self.width * self.height > i16::MAX as usize {
+            return invalid(format!("Map size {}x{} must be at least 4x4 and at most {} squares", self.width, self.height, i16::MAX));
+        }
+        if self.zones == 0 {
+            return invalid(String::from("At least one zone is needed"));
+        }
+        // Rectangle zones keep off the outer squares, yet the biggest inner zone takes 3/4 of the map
+        let inner = (self.width - 2) * (self.height - 2);
+        let biggest = (self.width * self.height) as f32 * 0.75;
+        if self.zones > 1 && self.settings.zone_shape == ZoneShape::Rects && (inner as f32) < biggest.floor() {
+            return invalid(format!("Map size {}x{} is too small for {} zones", self.width, self.height, self.zones));
+        }
+        let settings = &self.settings;
+        for (name, value) in [
+            ("drop_height", settings.drop_height as usize),
+            ("zone_every", settings.zone_every),
+            ("flyer_every", settings.flyer_every),
+        ] {
+            if value == 0 {
+                return invalid(format!("{} must not be zero", name));
+            }
+        }
+        // Below 2 rooks, bishops and queens cannot move at all
+        if settings.move_range_limit < 2 {
+            return invalid(format!("move_range_limit {} must be at least 2", settings.move_range_limit));
+        }
+        Ok(())
+    }
+
+    pub fn shape(&self) -> Coord {
+        Coord{x: self.width, y: self.height}
+    }
+
+    pub fn spawn(&self) -> World {
+        spawn_with_settings(
+            self.settings.clone(),
+            self.shape(),
+            self.zones,
+            &self.players,
+            self.seed.unwrap_or_else(rand::random),
+        )
+    }
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        GameConfig::new()
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Toml(toml::de::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(err) => write!(f, "Config io error: {}", err),
+            ConfigError::Json(err) => write!(f, "Config json error: {}", err),
+            ConfigError::Toml(err) => write!(f, "Config toml error: {}", err),
+            ConfigError::Invalid(message) => write!(f, "Invalid config: {}", message),
+        }
+    }
+}
+
+impl From<io::Error> for ConfigError {
+    fn from(err: io::Error) -> Self {
+        ConfigError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for ConfigError {
+    fn from(err: serde_json::Error) -> Self {
+        ConfigError::Json(err)
+    }
+}
+
+impl From<toml::de::Error> for ConfigError {
+    fn from(err: toml::de::Error) -> Self {
+        ConfigError::Toml(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_config_is_valid() {
+        assert!(GameConfig::new().validate().is_ok());
+    }
+
+    #[test]
+    fn rejects_unplayable_configs() {
+        let small = GameConfig::from_toml("width = 3\nheight = 3").unwrap();
+        assert!(matches!(small.validate(), Err(ConfigError::Invalid(_))));
+        let crowded = GameConfig::from_toml("width = 19\nheight = 12").unwrap();
+        assert!(matches!(crowded.validate(), Err(ConfigError::Invalid(_))));
+        for setting in ["drop_height", "zone_every", "flyer_every"] {
+            let config = GameConfig::from_toml(&format!("[settings]\n{} = 0", setting)).unwrap();
+            assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))), "{}", setting);
+        }
+        let short = GameConfig::from_toml("[settings]\nmove_range_limit = 1").unwrap();
+        assert!(matches!(short.validate(), Err(ConfigError::Invalid(_))));
+    }
+}
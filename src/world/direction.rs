@@ -1,4 +1,4 @@
-use rand::Rng;
+use rand::{Rng, RngCore};
 
 #[derive(Debug, Copy, Clone)]
 pub enum Direction {
@@ -44,8 +44,7 @@ impl Direction {
         ]
     }
 
-    pub fn rnd() -> Direction {
-        let mut rng = rand::thread_rng();
+    pub fn rnd(rng: &mut dyn RngCore) -> Direction {
         [
             Direction::North,
             Direction::NorthEast,
@@ -59,8 +58,7 @@ impl Direction {
 
     }
 
-    pub fn rnd_next(&self, ratio: u16) -> Direction {
-        let mut rng = rand::thread_rng();
+    pub fn rnd_next(&self, ratio: u16, rng: &mut dyn RngCore) -> Direction {
         match rng.gen_range(0, ratio + 2) {
             0 => self.neighbours()[0],
             1 => self.neighbours()[1],
@@ -80,7 +78,10 @@ impl Direction {
         let rank = self.as_rank();
         let dirs: Vec<Direction> = Direction::iterator()
             .enumerate()
-            .filter_map(|(idx, d)| if i16::abs(rank - (idx as i16)) % 8 == 1 { Some(d)} else { None  })
+            .filter_map(|(idx, d)| match (rank - (idx as i16)).rem_euclid(8) {
+                1 | 7 => Some(d),
+                _ => None,
+            })
             .collect();
         dirs
     }
@@ -1,8 +1,20 @@
 use std::char;
+use std::fmt::Write;
 
-use crate::world::Coord;
+use crate::world::{Coord, World};
+use crate::world::board::Board;
+use crate::world::pieces::{PieceType, NEUTRAL_PLAYER};
 use crate::world::player::{Player, PlayerState};
 
+// ANSI 256 colours
+const PLAYER_COLOURS: &[u8] = &[196, 33, 46, 226, 201, 51, 208, 129];
+const NEUTRAL_COLOUR: u8 = 250;
+const EMPTY_COLOUR: u8 = 240;
+const FLYER_PATH_COLOUR: u8 = 244;
+const FLYER_COLOUR: u8 = 231;
+const NEXT_ZONE_COLOUR: u8 = 24;
+const FOG_COLOUR: u8 = 245; // Darkens the longer a square has been fogged
+
 fn encode_ch(val: u16) -> String {
     if val > 9 {
     let c = char::from_u32((val + 55) as u32);
@@ -58,3 +70,62 @@ pub fn print_air(
     }
     print_board(&lvl);
 }
+
+fn glyph(kind: PieceType) -> char {
+    match kind {
+        PieceType::King => '♚',
+        PieceType::Queen => '♛',
+        PieceType::Rook => '♜',
+        PieceType::Bishop => '♝',
+        PieceType::Knight => '♞',
+        PieceType::Pawn => '♟',
+        PieceType::Empty => ' ',
+    }
+}
+
+fn player_colour(player: u16) -> u8 {
+    if player == NEUTRAL_PLAYER { return NEUTRAL_COLOUR; }
+    PLAYER_COLOURS[(player as usize - 1) % PLAYER_COLOURS.len()]
+}
+
+pub fn render_world(world: &World) -> String {
+    let shape = world.fog.shape();
+    let next_zone = world.fog.next_zone(true);
+    let flyer = if world.flyer.flying() { world.flyer.as_map() } else { world.fog.zones.new_with(0) };
+    let mut out = String::from("   ");
+    for x in 0..shape.x {
+        write!(out, "{} ", x % 10).unwrap();
+    }
+    out.push('\n');
+
+    for y in 0..shape.y {
+        write!(out, "{:>2} ", y).unwrap();
+        for x in 0..shape.x {
+            let background = if world.fog.fog[y][x] > 0 {
+                Some(FOG_COLOUR - world.fog.fog_curve[y][x].min(10) as u8)
+            } else if next_zone[y][x] > 0 {
+                Some(NEXT_ZONE_COLOUR)
+            } else {
+                None
+            };
+            let (colour, ch) = match world.pieces.get(&world.pieces_map[y][x]) {
+                Some(piece) if piece.alive => (player_colour(piece.player), glyph(piece.kind)),
+                _ => match flyer[y][x] {
+                    0 => (EMPTY_COLOUR, '.'),
+                    1 => (FLYER_PATH_COLOUR, '+'),
+                    _ => (FLYER_COLOUR, 'X'),
+                },
+            };
+            if let Some(background) = background {
+                write!(out, "\x1B[48;5;{}m", background).unwrap();
+            }
+            write!(out, "\x1B[38;5;{}m{} \x1B[0m", colour, ch).unwrap();
+        }
+        out.push('\n');
+    }
+    out
+}
+
+pub fn print_world(world: &World) {
+    print!("{}", render_world(world));
+}
\ No newline at end of file
@@ -1,5 +1,9 @@
+use rand::RngCore;
+use serde::{Serialize, Deserialize};
+
 use crate::world::position::Coord;
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Flyer {
     path: Vec<Coord>,
     idx: i16,
@@ -15,9 +19,9 @@ impl Flyer {
         }
     }
 
-    pub fn init(&mut self, shape: Coord, pather: fn(&mut Vec<Coord>, Coord)) {
+    pub fn init(&mut self, shape: Coord, pather: fn(&mut Vec<Coord>, Coord, &mut dyn RngCore), rng: &mut dyn RngCore) {
         self.map_shape = shape.clone();
-        pather(&mut self.path, shape);
+        pather(&mut self.path, shape, rng);
     }
 
     pub fn can_drop(&self) -> bool {
@@ -38,6 +42,10 @@ impl Flyer {
         Some(self.path[self.idx as usize])
     }
 
+    pub fn path(&self) -> &[Coord] {
+        &self.path
+    }
+
     pub fn tick(&mut self) {
         self.idx += 1;
     }
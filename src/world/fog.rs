@@ -1,3 +1,6 @@
+use rand::RngCore;
+use serde::{Serialize, Deserialize};
+
 use crate::world::board::Board;
 use crate::world::position::Coord;
 
@@ -9,6 +12,7 @@ pub enum FogState {
     Done,
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Fog {
     pub zones: Vec<Vec<u16>>,
     pub fog_curve: Vec<Vec<u16>>,
@@ -16,6 +20,8 @@ pub struct Fog {
     zone_rest: usize,
     fog_value: u16,
     active_zone: u16,
+    #[serde(default)]
+    done: bool,
 }
 
 impl Fog {
@@ -34,6 +40,7 @@ impl Fog {
             zone_rest: 0,
             active_zone: 0,
             fog_value: 0,
+            done: false,
         }
     }
 
@@ -44,6 +51,7 @@ impl Fog {
         }
         if self.fog_value == 0 {
             if self.active_zone == 0 {
+                self.done = true;
                 return FogState::Done;
             }
             self.active_zone -= 1;
@@ -52,6 +60,7 @@ impl Fog {
             self.fog_value -= 1;
             if self.fog_value == 0 {
                 if self.active_zone == 1 {
+                    self.done = true;
                     return FogState::Done;
                 }
                 self.zone_rest = next_rest;
@@ -86,6 +95,18 @@ impl Fog {
         ret
     }
 
+    pub fn active_zone(&self) -> u16 {
+        self.active_zone
+    }
+
+    pub fn fog_step(&self) -> u16 {
+        self.fog_value
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
     pub fn status(&self) -> String {
         format!("Zone {} / step {}", self.active_zone, self.fog_value)
     }
@@ -93,10 +114,11 @@ impl Fog {
     pub fn init(
         &mut self,
         nzones: u16,
-        init_zones: fn(&mut Vec<Vec<u16>>, u16),
+        init_zones: fn(&mut Vec<Vec<u16>>, u16, &mut dyn RngCore),
         init_fog: fn(&mut Vec<Vec<u16>>, &Vec<Vec<u16>>),
+        rng: &mut dyn RngCore,
     ) {
-        init_zones(&mut self.zones, nzones);
+        init_zones(&mut self.zones, nzones, rng);
         init_fog(&mut self.fog_curve, &self.zones);
         self.active_zone = self.zones.max_val() + 1;
     }
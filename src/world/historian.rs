@@ -1,22 +1,97 @@
-use crate::world::pieces::PieceType;
+use serde::{Serialize, Deserialize};
 
-#[derive(Debug)]
+use crate::world::{Action, MoveRejection};
+use crate::world::pieces::{PieceType, NEUTRAL_PLAYER};
+use crate::world::player::CheckState;
+use crate::world::position::Coord;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Event {
+    Drop{at: Coord, height: u16},
+    Fly{from: Coord, to: Coord},
+    Push{from: Coord, to: Coord, height: u16},
+    Fall{at: Coord, height: u16},
+    Slide{from: Coord, to: Coord},
+    Land{piece_id: u16, at: Coord},
+    Move{piece_id: u16, from: Coord, to: Coord},
+    Castle{piece_id: u16, from: Coord, to: Coord},
+    Rejected{from: Coord, to: Coord, rejection: MoveRejection},
+    Promote{piece_id: u16, at: Coord, kind: PieceType},
+    RejectedPromotion{at: Coord, kind: PieceType, rejection: MoveRejection},
+    Recruit{piece_id: u16, at: Coord},
+    Capture{piece_id: u16, kind: PieceType, at: Coord},
+    Check(CheckState),
+    LostInFog{piece_id: u16, at: Coord},
+    Eliminated{rank: u16},
+    ZoneShrink{zone: u16, step: u16},
+    ZoneActivated{zone: u16},
+    ZoneClosed{zone: u16},
+    FogDone,
+    Flyer{at: Coord},
+    ForcedDrop{at: Coord},
+    Winner{player: u16},
+}
+
+impl Event {
+    pub fn describe(&self) -> String {
+        match self {
+            Event::Drop{at, height} => format!("Drop -> {:?}:{}", at, height),
+            Event::Fly{from, to} => format!("Fly {:?} -> {:?}", from, to),
+            Event::Push{from, to, height} => format!("Pushed {:?} -> {:?}:{}", from, to, height),
+            Event::Fall{at, height} => format!("Fall -> {:?}:{}", at, height),
+            Event::Slide{from, to} => format!("Slide {:?} -> {:?}", from, to),
+            Event::Land{piece_id: _, at} => format!("Land -> {:?}", at),
+            Event::Move{piece_id: _, from, to} => format!("Move {:?} -> {:?}", from, to),
+            Event::Castle{piece_id: _, from, to} => format!("Castle {:?} -> {:?}", from, to),
+            Event::Rejected{from, to, rejection} => format!("Rejected {:?} -> {:?}: {:?}", from, to, rejection),
+            Event::Promote{piece_id: _, at, kind} => format!("Promote {:?} -> {:?}", at, kind),
+            Event::RejectedPromotion{at, kind, rejection} => format!(
+                "Rejected promotion {:?} -> {:?}: {:?}",
+                at,
+                kind,
+                rejection,
+            ),
+            Event::Recruit{piece_id: _, at} => format!("Recruit @ {:?}", at),
+            Event::Capture{piece_id: _, kind, at} => format!("Capture {:?} @ {:?}", kind, at),
+            Event::Check(check) => format!("{:?}", check),
+            Event::LostInFog{piece_id: _, at} => format!("Lost in fog @ {:?}", at),
+            Event::Eliminated{rank} => format!("Eliminated, placement {}", rank),
+            Event::ZoneShrink{zone, step} => format!("Zone {} shrinks, step {}", zone, step),
+            Event::ZoneActivated{zone} => format!("Zone {} activated", zone),
+            Event::ZoneClosed{zone} => format!("Zone {} closed", zone),
+            Event::FogDone => String::from("Fog done"),
+            Event::Flyer{at} => format!("Flyer @ {:?}", at),
+            Event::ForcedDrop{at} => format!("Forced drop @ {:?}", at),
+            Event::Winner{player} => format!("Winner player {}", player),
+        }
+    }
+
+    pub fn is_capture(&self) -> bool {
+        matches!(self, Event::Capture{..})
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Record {    
     pub player: u16,
     pub tick: usize,
     pub piece: PieceType,
-    pub event: String,
+    pub event: Event,
 }
 
 impl Record {
     pub fn summarize(&self) -> String {
-        format!("Tick {}, player {}: {:?} {}", self.tick, self.player, self.piece, self.event)
+        if self.player == NEUTRAL_PLAYER {
+            return format!("Tick {}, world: {}", self.tick, self.event.describe());
+        }
+        format!("Tick {}, player {}: {:?} {}", self.tick, self.player, self.piece, self.event.describe())
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Historian {
     player_record: Vec<Record>,
+    tick_actions: Vec<Vec<Action>>,
     print_events: bool
 }
 
@@ -24,6 +99,7 @@ impl Historian {
     pub fn new(print_events: bool) -> Self {
         Historian{
             player_record: Vec::new(),
+            tick_actions: Vec::new(),
             print_events,
         }
     }
@@ -33,10 +109,53 @@ impl Historian {
         player: u16,
         tick: usize,
         piece: PieceType,
-        event: String,
+        event: Event,
     ) {
         let record = Record{player, tick, piece, event};
         if self.print_events { println!("{}", record.summarize()); }
         self.player_record.push(record)
     }
+
+    pub fn record_world(&mut self, tick: usize, event: Event) {
+        self.record_player(NEUTRAL_PLAYER, tick, PieceType::Empty, event)
+    }
+
+    pub fn records(&self) -> &Vec<Record> {
+        &self.player_record
+    }
+
+    pub fn by_player(&self, player: u16) -> Vec<&Record> {
+        self.player_record.iter().filter(|r| r.player == player).collect()
+    }
+
+    pub fn by_ticks(&self, from_tick: usize, to_tick: usize) -> Vec<&Record> {
+        self.player_record.iter().filter(|r| r.tick >= from_tick && r.tick < to_tick).collect()
+    }
+
+    pub fn world_events(&self) -> Vec<&Record> {
+        self.by_player(NEUTRAL_PLAYER)
+    }
+
+    pub fn captures(&self) -> Vec<&Record> {
+        self.player_record.iter().filter(|r| r.event.is_capture()).collect()
+    }
+
+    pub fn record_actions(&mut self, tick: usize, actions: Vec<Action>) {
+        self.tick_actions.resize(tick, Vec::new());
+        self.tick_actions.push(actions);
+    }
+
+    pub fn actions(&self, from_tick: usize, to_tick: usize) -> Vec<Vec<Action>> {
+        let to_tick = to_tick.min(self.tick_actions.len());
+        let from_tick = from_tick.min(to_tick);
+        self.tick_actions[from_tick..to_tick].to_vec()
+    }
+
+    pub fn printing(&self) -> bool {
+        self.print_events
+    }
+
+    pub fn set_printing(&mut self, print_events: bool) {
+        self.print_events = print_events;
+    }
 }
\ No newline at end of file
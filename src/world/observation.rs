@@ -0,0 +1,246 @@
+use serde::{Serialize, Deserialize};
+
+use crate::world::{Action, ActionError, World};
+use crate::world::board::Board;
+use crate::world::historian::Historian;
+use crate::world::pieces::PieceType;
+use crate::world::player::{Player, PlayerState, CheckState};
+use crate::world::position::{Coord, Positional};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ObservedPiece {
+    pub piece_id: u16,
+    pub kind: PieceType,
+    pub player: u16,
+    pub at: Coord,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ObservedPlayer {
+    pub player_id: u16,
+    pub game_name: String,
+    pub score: u16,
+    pub alive: bool,
+}
+
+// What a single player is allowed to know about the world
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Observation {
+    pub player_id: u16,
+    pub tick: usize,
+    pub state: PlayerState,
+    pub check: CheckState,
+    pub score: u16,
+    pub visible: Vec<Vec<bool>>,
+    pub pieces: Vec<ObservedPiece>,
+    pub players: Vec<ObservedPlayer>,
+    pub fog: Vec<Vec<u16>>,
+    pub zones: Vec<Vec<u16>>,
+    pub next_zone: Vec<Vec<u16>>,
+    pub fog_status: String,
+    pub flyer: Option<Coord>,
+    pub flyer_path: Vec<Coord>,
+}
+
+// Changes since the previous observation of the same player
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ObservationDiff {
+    pub tick: usize,
+    pub state: PlayerState,
+    pub check: CheckState,
+    pub score: u16,
+    pub pieces: Vec<ObservedPiece>,
+    pub removed: Vec<u16>,
+    pub fogged: Vec<Coord>,
+    pub fog_status: String,
+    pub flyer: Option<Coord>,
+    // Only sent when the route is new to the player
+    pub flyer_path: Option<Vec<Coord>>,
+}
+
+// Everything on and above the board, for spectators who do not play
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpectatorView {
+    pub tick: usize,
+    pub pieces: Vec<ObservedPiece>,
+    pub players: Vec<Player>,
+    pub fog: Vec<Vec<u16>>,
+    pub zones: Vec<Vec<u16>>,
+    pub next_zone: Vec<Vec<u16>>,
+    pub fog_status: String,
+    pub flyer: Option<Coord>,
+    pub flyer_path: Vec<Coord>,
+}
+
+impl Observation {
+    pub fn diff(&self, previous: Option<&Observation>) -> ObservationDiff {
+        let (pieces, removed, fogged) = match previous {
+            Some(previous) => (
+                self.pieces
+                    .iter()
+                    .filter(|piece| !previous.pieces.contains(piece))
+                    .cloned()
+                    .collect(),
+                previous.pieces
+                    .iter()
+                    .filter(|old| !self.pieces.iter().any(|piece| piece.piece_id == old.piece_id))
+                    .map(|old| old.piece_id)
+                    .collect(),
+                self.fog.coords_when(1, &previous.fog, 0),
+            ),
+            None => (self.pieces.clone(), Vec::new(), self.fog.coords_of(1)),
+        };
+        ObservationDiff{
+            tick: self.tick,
+            state: self.state,
+            check: self.check,
+            score: self.score,
+            pieces,
+            removed,
+            fogged,
+            fog_status: self.fog_status.clone(),
+            flyer: self.flyer,
+            flyer_path: match previous {
+                Some(previous) if previous.flyer_path == self.flyer_path => None,
+                _ => Some(self.flyer_path.clone()),
+            },
+        }
+    }
+}
+
+impl World {
+    fn vision(&self, player_id: u16) -> Vec<Vec<bool>> {
+        let shape = self.fog.shape();
+        let mut visible = self.pieces_map.new_with(false);
+        let mut eyes: Vec<Coord> = self.pieces
+            .values()
+            .filter(|p| p.alive && p.player == player_id)
+            .filter_map(|p| p.position().copied())
+            .collect();
+        if let PlayerState::Falling(_h, coord) = self.players[&player_id].state {
+            eyes.push(coord);
+        }
+
+        let radius = self.settings.vision_radius;
+        for eye in eyes {
+            let lower_left = Coord{x: eye.x.saturating_sub(radius), y: eye.y.saturating_sub(radius)};
+            let upper_right = Coord{x: eye.x + radius + 1, y: eye.y + radius + 1}.clamp(&shape);
+            for row in visible.iter_mut().take(upper_right.y).skip(lower_left.y) {
+                for cell in row.iter_mut().take(upper_right.x).skip(lower_left.x) {
+                    *cell = true;
+                }
+            }
+        }
+        visible
+    }
+
+    fn observed_pieces(&self, seen: &dyn Fn(Coord) -> bool) -> Vec<ObservedPiece> {
+        let mut pieces: Vec<ObservedPiece> = self.pieces
+            .iter()
+            .filter(|(_, piece)| piece.alive)
+            .filter_map(|(piece_id, piece)| {
+                let at = *piece.position()?;
+                if !seen(at) { return None; }
+                Some(ObservedPiece{piece_id: *piece_id, kind: piece.kind, player: piece.player, at})
+            })
+            .collect();
+        pieces.sort_unstable_by_key(|piece| piece.piece_id);
+        pieces
+    }
+
+    // The world as the player sees it, pieces out of sight are left off the board
+    fn observed_world(&self, player_id: u16) -> World {
+        let visible = self.vision(player_id);
+        let mut world = World{
+            settings: self.settings.clone(),
+            fog: self.fog.clone(),
+            pieces: self.pieces.clone(),
+            pieces_map: self.pieces_map.clone(),
+            flyer: self.flyer.clone(),
+            players: self.players.clone(),
+            req_air_action: Vec::new(),
+            req_board_action: Vec::new(),
+            alive: self.alive,
+            tick: self.tick,
+            seed: self.seed,
+            historian: Historian::new(false),
+        };
+        for piece in world.pieces.values_mut() {
+            let at = match piece.position() {
+                Some(at) if !visible[at.y][at.x] => *at,
+                _ => continue,
+            };
+            world.pieces_map[at.y][at.x] = 0;
+            piece.alive = false;
+        }
+        world
+    }
+
+    // Moves are judged on what the player can see, so rejections give nothing away.
+    // Moves spoiled by unseen pieces are queued and rejected on the tick instead.
+    pub fn request_observed_action(&mut self, action: Action) -> Result<(), ActionError> {
+        let user = match action {
+            Action::Move(user, _from, _to) => user,
+            _ => return self.request_action(action),
+        };
+        if !self.players.contains_key(&user) { return Err(ActionError::UnknownPlayer); }
+        self.observed_world(user).request_action(action)?;
+        self.req_board_action.retain(|a| a.user() != user);
+        self.req_board_action.push(action);
+        Ok(())
+    }
+
+    fn flyer_path(&self) -> Vec<Coord> {
+        if self.flyer.flying() { self.flyer.path().to_vec() } else { Vec::new() }
+    }
+
+    pub fn observation(&self, player_id: u16) -> Option<Observation> {
+        let player = self.players.get(&player_id)?;
+        let visible = self.vision(player_id);
+        let pieces = self.observed_pieces(&|at| visible[at.y][at.x]);
+
+        let mut players: Vec<ObservedPlayer> = self.players
+            .values()
+            .map(|p| ObservedPlayer{
+                player_id: p.player_id,
+                game_name: p.game_name.clone(),
+                score: p.score,
+                alive: p.state.is_alive(),
+            })
+            .collect();
+        players.sort_unstable_by_key(|p| p.player_id);
+
+        Some(Observation{
+            player_id,
+            tick: self.tick,
+            state: player.state,
+            check: player.check,
+            score: player.score,
+            visible,
+            pieces,
+            players,
+            fog: self.fog.fog.clone(),
+            zones: self.fog.zones.clone(),
+            next_zone: self.fog.next_zone(false),
+            fog_status: self.fog.status(),
+            flyer: self.flyer.coord(),
+            flyer_path: self.flyer_path(),
+        })
+    }
+
+    pub fn spectator_view(&self) -> SpectatorView {
+        let mut players: Vec<Player> = self.players.values().cloned().collect();
+        players.sort_unstable_by_key(|p| p.player_id);
+        SpectatorView{
+            tick: self.tick,
+            pieces: self.observed_pieces(&|_| true),
+            players,
+            fog: self.fog.fog.clone(),
+            zones: self.fog.zones.clone(),
+            next_zone: self.fog.next_zone(false),
+            fog_status: self.fog.status(),
+            flyer: self.flyer.coord(),
+            flyer_path: self.flyer_path(),
+        }
+    }
+}
@@ -1,9 +1,11 @@
+use serde::{Serialize, Deserialize};
+
 use crate::world::position::{Coord, Offset, Positional};
 use crate::world::direction::Direction;
-use crate::world::World;
+use crate::world::{Placement, World};
 use crate::world::board::Board;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub enum PieceType {
     Empty,
     Rook,
@@ -14,50 +16,87 @@ pub enum PieceType {
     Pawn,
 }
 
-const MOVE_RANGE_LIMIT: i16 = 9;
+pub const NEUTRAL_PLAYER: u16 = 0;
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PieceValues {
+    pub pawn: u16,
+    pub knight: u16,
+    pub bishop: u16,
+    pub rook: u16,
+    pub queen: u16,
+    pub king: u16,
+}
+
+impl PieceValues {
+    pub fn new() -> PieceValues {
+        PieceValues{pawn: 1, knight: 3, bishop: 3, rook: 5, queen: 9, king: 20}
+    }
+
+    pub fn of(&self, kind: PieceType) -> u16 {
+        match kind {
+            PieceType::Bishop => self.bishop,
+            PieceType::Empty => 0,
+            PieceType::King => self.king,
+            PieceType::Knight => self.knight,
+            PieceType::Pawn => self.pawn,
+            PieceType::Queen => self.queen,
+            PieceType::Rook => self.rook,
+        }
+    }
+}
+
+impl Default for PieceValues {
+    fn default() -> Self {
+        PieceValues::new()
+    }
+}
 
 impl PieceType {
-    pub fn intermediat_steps(&self, from: Coord, to: Coord) -> Option<Vec<Coord>> {
+    pub fn intermediat_steps(&self, from: Coord, to: Coord, range_limit: i16) -> Option<Vec<Coord>> {
         let off: Offset = to - from;
         match self {
             PieceType::Empty => None,
             PieceType::King => if off.chebyshev() == 1 { from.steps(&to) } else { None },
             PieceType::Pawn => if off.chebyshev() == 1 { from.steps(&to) } else { None },
             PieceType::Knight => if off.chebyshev() == 2 && off.skew() == 1 { from.steps(&to) } else { None },
-            PieceType::Bishop => if off.chebyshev() < MOVE_RANGE_LIMIT && off.skew() == 0 { from.steps(&to) } else { None },
+            PieceType::Bishop => if off.chebyshev() < range_limit && off.skew() == 0 { from.steps(&to) } else { None },
             PieceType::Rook => {
                 let c = off.chebyshev();
-                if c < MOVE_RANGE_LIMIT && off.manhattan() == c { from.steps(&to) } else { None }
+                if c < range_limit && off.manhattan() == c { from.steps(&to) } else { None }
             },
             PieceType::Queen => {
                 let c = off.chebyshev();
-                if c < MOVE_RANGE_LIMIT && (off.skew() == 0 || off.manhattan() == c) {
+                if c < range_limit && (off.skew() == 0 || off.manhattan() == c) {
                     from.steps(&to)
                 } else { None }
             },
         }
     }
 
-    pub fn value(&self) -> u16 {
-        match self {
-            PieceType::Bishop => 3,
-            PieceType::Empty => 0,
-            PieceType::King => 20,
-            PieceType::Knight => 3,
-            PieceType::Pawn => 1,
-            PieceType::Queen => 9,
-            PieceType::Rook => 5,
-        }
-    }
-
     pub fn is_rook(&self) -> bool {
         match self {
             PieceType::Rook => true,
             _ => false
         }
     }
+
+    pub fn is_king(&self) -> bool {
+        matches!(self, PieceType::King)
+    }
+
+    pub fn is_promotion(&self) -> bool {
+        matches!(self, PieceType::Rook | PieceType::Knight | PieceType::Bishop | PieceType::Queen)
+    }
+
+    // Default capture value, games score with WorldSettings::piece_values
+    pub fn value(&self) -> u16 {
+        PieceValues::new().of(*self)
+    }
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Piece {
     pub kind: PieceType,
     pub player: u16,
@@ -80,6 +119,10 @@ impl Piece {
         if self.alive {self.history.last()} else { None }
     }
 
+    pub fn is_neutral(&self) -> bool {
+        self.player == NEUTRAL_PLAYER
+    }
+
     pub fn unmoved(&self) -> bool {
         self.history.len() == 1
     }
@@ -102,6 +145,27 @@ impl Piece {
         cross
     }
 
+    pub fn pawn_distance(&self) -> i16 {
+        let directions = self.pawn_direction();
+        if directions.len() != 1 { return 0; }
+        // Distance travelled along the locked direction
+        let off: Offset = *self.history.last().unwrap() - self.history[0];
+        match directions[0] {
+            Direction::North => -off.y,
+            Direction::South => off.y,
+            Direction::East => off.x,
+            Direction::West => -off.x,
+            _ => 0,
+        }
+    }
+
+    pub fn can_promote(&self, distance: i16) -> bool {
+        match self.kind {
+            PieceType::Pawn => self.alive && self.pawn_distance() >= distance,
+            _ => false,
+        }
+    }
+
     pub fn my_king_is_checked(&self, world: &World) -> bool {
         let king_id = world.players.get(&self.player).unwrap().king_id;
         let king: &Piece = world.pieces.get(&king_id).unwrap();
@@ -113,12 +177,12 @@ impl Piece {
     pub fn can_move_to(&self, world: &World, coord: &Coord) -> bool {
         let pos = self.position().unwrap();
         let off: Offset = *coord - *pos;
-        let checked = self.my_king_is_checked(world);
-        
-        // May not self take
+        let range_limit = world.settings.move_range_limit;
+
+        // May not self take, nor take neutral pieces
         match world.pieces.get(&world.pieces_map[coord.y][coord.x]) {
             Some(target_piece) => {
-                if target_piece.player == self.player {
+                if target_piece.player == self.player || target_piece.is_neutral() {
                     return false
                 }
             },
@@ -135,7 +199,7 @@ impl Piece {
                 }
 
                 // Else must be Castling
-                if !self.unmoved() || checked { return false };
+                if !self.unmoved() || self.my_king_is_checked(world) { return false };
                 // Castling always 2 steps towards rook
                 if !(off.chebyshev() == 2) {
                     return false;
@@ -150,8 +214,8 @@ impl Piece {
                     }
                 }
 
-                // Project on world to first piece in direction
-                match world.pieces_map.find_first(&coord, dir) {
+                // Project on world to first piece in direction, squares passed must be empty
+                match world.pieces_map.find_first(pos, dir) {
                     Some(other_id) => {
                         let other = world.pieces.get(&other_id).unwrap();
                         // First piece find must be ours and umoved too
@@ -159,7 +223,7 @@ impl Piece {
                         // Must not be too far
                         let other_off: Offset = *other.position().unwrap() - *pos;
                         let dist = other_off.chebyshev();
-                        if dist >= MOVE_RANGE_LIMIT { return false; }
+                        if dist >= range_limit { return false; }
                         // Other must be at least 3 steps away since we're moving 2
                         if dist < 3 { return false; }
                         // Check other is rook
@@ -169,7 +233,7 @@ impl Piece {
                 }
             },
             PieceType::Pawn => {
-                if checked || off.manhattan() > 2 { return false; }
+                if off.manhattan() > 2 { return false; }
 
                 let off_dir = off.as_direction().unwrap();
                 let directions = self.pawn_direction();
@@ -193,23 +257,23 @@ impl Piece {
                     .iter()
                     .any(|c: &Coord | c.x == coord.x && c.y == coord.y);
             },
-            PieceType::Knight => !checked && off.chebyshev() == 2 && off.skew() == 1,
+            PieceType::Knight => off.chebyshev() == 2 && off.skew() == 1,
             PieceType::Bishop => {
-                if off.chebyshev() < MOVE_RANGE_LIMIT && off.skew() == 0 {
+                if off.chebyshev() < range_limit && off.skew() == 0 {
                     return world.no_piece_between(pos, coord)
                 }
                 false
             },
             PieceType::Rook => {
                 let c = off.chebyshev();
-                if c < MOVE_RANGE_LIMIT && off.manhattan() == c {
+                if c < range_limit && off.manhattan() == c {
                     return world.no_piece_between(pos, coord)
                 }
                 false
             },
             PieceType::Queen => {
                 let c = off.chebyshev();
-                if c < MOVE_RANGE_LIMIT && (off.skew() == 0 || off.manhattan() == c) {
+                if c < range_limit && (off.skew() == 0 || off.manhattan() == c) {
                     return world.no_piece_between(pos, coord)
                 }
                 false
@@ -218,19 +282,60 @@ impl Piece {
         }
     }
 
-    fn others_threatening_me_at(&self, world: &World, pos: &Coord) -> Vec<u16> {
+    pub fn moves(&self, world: &World) -> Vec<Coord> {
+        let pos = self.position().unwrap();
         let shape = world.fog.shape();
-        let lower_left = pos
-            .translate_n_direction(Direction::NorthWest, MOVE_RANGE_LIMIT - 1)
-            .clamp(&shape);
+        let mut coords = Vec::new();
+        match self.kind {
+            PieceType::Empty => (),
+            PieceType::Knight => coords = pos.knight_offsets(),
+            PieceType::King | PieceType::Pawn => {
+                // Steps, takes, castling and pawn first moves are all within two steps
+                for dir in Direction::iterator() {
+                    let mut current = *pos;
+                    for _ in 0..2 {
+                        current = current.translate_direction(dir);
+                        coords.push(current);
+                    }
+                }
+            },
+            PieceType::Bishop | PieceType::Rook | PieceType::Queen => {
+                for dir in Direction::iterator() {
+                    let mut current = *pos;
+                    for _ in 1..world.settings.move_range_limit {
+                        current = current.translate_direction(dir);
+                        if !current.is_inside(&shape) { break; }
+                        coords.push(current);
+                        if world.pieces_map[current.y][current.x] != 0 { break; }
+                    }
+                }
+            },
+        }
+        coords.retain(|c| c.is_inside(&shape) && self.can_move_to(world, c));
+        coords
+    }
+
+    pub fn others_threatening_me_at(&self, world: &World, pos: &Coord) -> Vec<u16> {
+        self.others_threatening_me_after(world, pos, &[])
+    }
+
+    // As if the placements were made, without changing the board
+    pub fn others_threatening_me_after(&self, world: &World, pos: &Coord, placements: &[Placement]) -> Vec<u16> {
+        let shape = world.fog.shape();
+        // Knights threaten from 2 squares away whatever the range limit
+        let range = (world.settings.move_range_limit - 1).max(2);
+        let reach = range as usize;
+        let lower_left = Coord{x: pos.x.saturating_sub(reach), y: pos.y.saturating_sub(reach)};
         let upper_right = pos
-            .translate_n_direction(Direction::SouthEast, MOVE_RANGE_LIMIT - 1)
+            .translate_n_direction(Direction::SouthEast, range)
             .clamp(&shape);
         let mut ids = Vec::new();
         for idx in world.pieces_map.find_all(&lower_left, &upper_right) {
             let piece = world.pieces.get(&idx).unwrap();
-            if piece.player == self.player { continue; }
-            if piece.threatening(world).iter().any(|p| p.x == pos.x && p.y == pos.y) {
+            if piece.player == self.player || piece.is_neutral() { continue; }
+            // Taken by one of the placements
+            if world.piece_at_after(piece.position().unwrap(), placements) != idx { continue; }
+            if piece.threatening_after(world, placements).iter().any(|p| p.x == pos.x && p.y == pos.y) {
                 ids.push(idx);
             }
         }
@@ -238,6 +343,10 @@ impl Piece {
     }
 
     fn threatening(&self, world: &World) -> Vec<Coord> {
+        self.threatening_after(world, &[])
+    }
+
+    fn threatening_after(&self, world: &World, placements: &[Placement]) -> Vec<Coord> {
         let mut coords = Vec::new();
         let pos = self.position().unwrap();
 
@@ -249,16 +358,18 @@ impl Piece {
             PieceType::Pawn | PieceType::Knight | PieceType::Empty => Vec::new(),
         };
         let reach = match self.kind {
-            PieceType::Bishop | PieceType::Rook | PieceType::Queen => MOVE_RANGE_LIMIT - 1,
-            PieceType::Knight => 1,
+            PieceType::Bishop | PieceType::Rook | PieceType::Queen => world.settings.move_range_limit - 1,
+            PieceType::King => 1,
             _ => 0,
         };
 
+        let shape = world.fog.shape();
         for dir in directions {
             let mut current: Coord = pos.clone();
             for _ in 0..reach{
                 current = current.translate_direction(dir);
-                let pid = world.pieces_map[current.y][current.x];
+                if !current.is_inside(&shape) { break; }
+                let pid = world.piece_at_after(&current, placements);
                 if pid > 0 {
                     if world.pieces.get(&pid).unwrap().player != self.player {
                         coords.push(current);
@@ -270,12 +381,11 @@ impl Piece {
         }
 
         // Special threats
-        let shape = world.fog.shape();
         match self.kind {
             PieceType::Knight => {
                 for coord in pos.knight_offsets() {
                     if !coord.is_inside(&shape) { continue; }
-                    let pid = world.pieces_map[coord.y][coord.x];
+                    let pid = world.piece_at_after(&coord, placements);
                     if pid != 0 && world.pieces.get(&pid).unwrap().player == self.player {
                         continue;
                     }
@@ -287,7 +397,7 @@ impl Piece {
                     for dir in cardinal.neighbours() {
                         let coord = pos.translate_direction(dir);
                         if coord.is_inside(&shape) {
-                            let pid = world.pieces_map[coord.y][coord.x];
+                            let pid = world.piece_at_after(&coord, placements);
                             if pid != 0 && world.pieces.get(&pid).unwrap().player != self.player {
                                 coords.push(coord);
                             }
@@ -1,8 +1,10 @@
+use rand::RngCore;
+use serde::{Serialize, Deserialize};
 use rand::seq::SliceRandom;
 
 use crate::world::position::Coord;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub enum PlayerState {
     Flying,
     Falling(u16, Coord), // height and position
@@ -10,6 +12,14 @@ pub enum PlayerState {
     Dead(u16), // Includes the rank
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum CheckState {
+    Safe,
+    Check,
+    Checkmate,
+    Stalemate,
+}
+
 impl PlayerState {
     pub fn is_flying(&self) -> bool {
         match self {
@@ -55,7 +65,7 @@ impl PlayerState {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Player {
     pub player_id: u16,
     pub king_id: u16,
@@ -63,6 +73,8 @@ pub struct Player {
     pub user_name: String,
     pub score: u16,
     pub state: PlayerState,
+    pub check: CheckState,
+    pub fog_ticks: usize, // Consecutive ticks the king spent in fog
 }
 
 impl Player {
@@ -74,6 +86,8 @@ impl Player {
             user_name: user_name.clone(),
             score: 0,
             state: PlayerState::Flying,
+            check: CheckState::Safe,
+            fog_ticks: 0,
         }
     }
 
@@ -113,12 +127,11 @@ pub struct GamerNamer {
 }
 
 impl GamerNamer {
-    pub fn new() -> Self {
-        let mut rng = rand::thread_rng();
+    pub fn new(rng: &mut dyn RngCore) -> Self {
         let mut adj_idxs: Vec<usize> = (0..GAME_NAME_ADJ.len()).collect();
-        adj_idxs.shuffle(&mut rng);
+        adj_idxs.shuffle(rng);
         let mut noun_idxs: Vec<usize> = (0..GAME_NAME_NOUN.len()).collect();
-        noun_idxs.shuffle(&mut rng);
+        noun_idxs.shuffle(rng);
         GamerNamer{
             adj_idxs,
             noun_idxs,
@@ -1,11 +1,13 @@
 use std::ops::Sub;
 use std::cmp::max;
 
+use serde::{Serialize, Deserialize};
+
 use crate::world::direction::Direction;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Coord {pub x: usize, pub y: usize}
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct Offset {pub x: i16, pub y: i16}
 
 pub trait Positional {
@@ -44,7 +46,7 @@ impl Positional for Coord {
                 if i32::abs(dx) + i32::abs(dy) != 3 { continue; }
                 if dx == 0 || self.x as i32 + dx < 0 { continue; }
 
-                coords.push(Coord{x: self.x + dx as usize, y: self.y + dy as usize});
+                coords.push(self.translate(Offset{x: dx as i16, y: dy as i16}));
             }
         }
         coords
@@ -103,9 +105,11 @@ impl Positional for Coord {
     }
 
     fn steps(&self, other: &Self) -> Option<Vec<Coord>> {
-        let off: Offset = *self - *other;
-        let s = off.skew();
-        if s > 1 {
+        let off: Offset = *other - *self;
+        if off.chebyshev() == 2 && off.skew() == 1 {
+            // Knight jumps have no steps in between
+            Some(Vec::new())
+        } else if off.skew() != 0 && off.x != 0 && off.y != 0 {
             None
         } else {
             let dir = off.direction();
@@ -0,0 +1,210 @@
+use std::fmt;
+use std::path::Path;
+
+use serde::{Serialize, Deserialize};
+
+use crate::world::{World, Action, ActionError};
+use crate::world::snapshot::{self, SnapshotError};
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Replay {
+    initial: World,
+    ticks: Vec<Vec<Action>>,
+    last: World,
+}
+
+impl Replay {
+    pub fn new(initial: &World, played: &World) -> Self {
+        Replay{
+            initial: initial.clone(),
+            ticks: played.historian.actions(initial.tick, played.tick),
+            last: played.clone(),
+        }
+    }
+
+    pub fn ticks(&self) -> usize {
+        self.ticks.len()
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SnapshotError> {
+        snapshot::write(self, path)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Replay, SnapshotError> {
+        snapshot::read(path)
+    }
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Rejected(usize, Action, ActionError),
+    Mismatch(usize),
+    Snapshot(SnapshotError),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::Rejected(tick, action, err) => write!(
+                f,
+                "Replay diverged at tick {}: {:?} rejected with {:?}",
+                tick,
+                action,
+                err,
+            ),
+            ReplayError::Mismatch(tick) => write!(f, "Replay final state differs at tick {}", tick),
+            ReplayError::Snapshot(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<SnapshotError> for ReplayError {
+    fn from(err: SnapshotError) -> Self {
+        ReplayError::Snapshot(err)
+    }
+}
+
+pub struct ReplayRunner {
+    replay: Replay,
+    world: World,
+    step: usize,
+}
+
+impl ReplayRunner {
+    pub fn new(replay: Replay) -> Self {
+        let world = replay.initial.clone();
+        ReplayRunner{replay, world, step: 0}
+    }
+
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    pub fn step(&self) -> usize {
+        self.step
+    }
+
+    pub fn at_end(&self) -> bool {
+        self.step >= self.replay.ticks()
+    }
+
+    pub fn step_forward(&mut self) -> Result<bool, ReplayError> {
+        if self.at_end() { return Ok(false); }
+        for action in self.replay.ticks[self.step].iter() {
+            if let Err(err) = self.world.request_action(*action) {
+                return Err(ReplayError::Rejected(self.world.tick, *action, err));
+            }
+        }
+        self.world.do_tick();
+        self.step += 1;
+        Ok(true)
+    }
+
+    pub fn step_back(&mut self) -> Result<bool, ReplayError> {
+        if self.step == 0 { return Ok(false); }
+        self.seek(self.step - 1)?;
+        Ok(true)
+    }
+
+    pub fn seek(&mut self, step: usize) -> Result<(), ReplayError> {
+        if step < self.step {
+            // Worlds only run forward, so re-simulate quietly from the start
+            let printing = self.world.historian.printing();
+            self.world = self.replay.initial.clone();
+            self.step = 0;
+            self.world.historian.set_printing(false);
+            let result = self.seek(step);
+            self.world.historian.set_printing(printing);
+            return result;
+        }
+        while self.step < step && self.step_forward()? {}
+        Ok(())
+    }
+
+    pub fn verify(&mut self) -> Result<(), ReplayError> {
+        self.seek(self.replay.ticks())?;
+        if self.world.to_json()? != self.replay.last.to_json()? {
+            return Err(ReplayError::Mismatch(self.world.tick));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    use super::*;
+    use crate::world::{spawn_with_settings, WorldSettings};
+    use crate::world::historian::Event;
+    use crate::world::player::PlayerState;
+    use crate::world::position::{Coord, Offset};
+
+    fn new_world(seed: u64) -> World {
+        let mut settings = WorldSettings::new();
+        settings.verbose = false;
+        let players: Vec<String> = ["Ann", "Bo", "Cy"].iter().map(|name| String::from(*name)).collect();
+        spawn_with_settings(settings, Coord{x: 24, y: 12}, 3, &players, seed)
+    }
+
+    // Random but seeded players, returns the world before and after play
+    fn play(seed: u64, ticks: usize) -> (World, World) {
+        let mut world = new_world(seed);
+        let initial = world.clone();
+        let mut rng = StdRng::seed_from_u64(seed);
+        for _ in 0..ticks {
+            if !world.is_alive() { break; }
+            for uid in 1..=3 {
+                let action = match world.players[&uid].state {
+                    PlayerState::Flying if rng.gen_bool(0.2) => Action::Drop(uid),
+                    PlayerState::Falling(..) => Action::Fly(uid, Offset{x: rng.gen_range(-1, 2), y: rng.gen_range(-1, 2)}),
+                    PlayerState::Boarded => {
+                        let moves = world.legal_moves(uid);
+                        if moves.is_empty() { continue; }
+                        let (from, to) = moves[rng.gen_range(0, moves.len())];
+                        Action::Move(uid, from, to)
+                    },
+                    _ => continue,
+                };
+                world.request_action(action).ok();
+            }
+            world.do_tick();
+        }
+        (initial, world)
+    }
+
+    #[test]
+    fn same_seed_and_actions_give_same_world() {
+        for seed in 0..5 {
+            let (_, first) = play(seed, 200);
+            let (_, second) = play(seed, 200);
+            assert_eq!(first.to_json().unwrap(), second.to_json().unwrap(), "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn replays_verify_against_played_games() {
+        for seed in 0..20 {
+            let (initial, played) = play(seed, 300);
+            assert!(played.historian().records().iter().any(|r| matches!(r.event, Event::Move{..})));
+            let replay = Replay::new(&initial, &played);
+            assert!(replay.ticks() > 0);
+            let mut runner = ReplayRunner::new(replay);
+            if let Err(err) = runner.verify() {
+                panic!("seed {}: {}", seed, err);
+            }
+        }
+    }
+
+    #[test]
+    fn seek_back_matches_playing_forward() {
+        let (initial, played) = play(7, 120);
+        let mut runner = ReplayRunner::new(Replay::new(&initial, &played));
+        runner.seek(60).unwrap();
+        let forward = runner.world().to_json().unwrap();
+        runner.seek(100).unwrap();
+        runner.seek(60).unwrap();
+        assert_eq!(runner.world().to_json().unwrap(), forward);
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+
+use serde::{Serialize, Serializer, Deserialize};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::world::World;
+
+const SNAPSHOT_VERSION: u16 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct Snapshot<W> {
+    version: u16,
+    world: W,
+}
+
+// Keeps snapshots of the same world identical on disk
+pub fn ordered<S: Serializer, V: Serialize>(map: &HashMap<u16, V>, serializer: S) -> Result<S::Ok, S::Error> {
+    map.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
+}
+
+fn from_snapshot<T: DeserializeOwned>(snapshot: Snapshot<Value>) -> Result<T, SnapshotError> {
+    if snapshot.version != SNAPSHOT_VERSION {
+        return Err(SnapshotError::Version(snapshot.version));
+    }
+    Ok(serde_json::from_value(snapshot.world)?)
+}
+
+pub fn write<T: Serialize, P: AsRef<Path>>(content: &T, path: P) -> Result<(), SnapshotError> {
+    let writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer(writer, &Snapshot{version: SNAPSHOT_VERSION, world: content})?;
+    Ok(())
+}
+
+pub fn read<T: DeserializeOwned, P: AsRef<Path>>(path: P) -> Result<T, SnapshotError> {
+    let reader = BufReader::new(File::open(path)?);
+    from_snapshot(serde_json::from_reader(reader)?)
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    Format(serde_json::Error),
+    Version(u16),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::Io(err) => write!(f, "Snapshot io error: {}", err),
+            SnapshotError::Format(err) => write!(f, "Snapshot format error: {}", err),
+            SnapshotError::Version(version) => write!(
+                f,
+                "Snapshot version {} not supported, expected {}",
+                version,
+                SNAPSHOT_VERSION,
+            ),
+        }
+    }
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(err: io::Error) -> Self {
+        SnapshotError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for SnapshotError {
+    fn from(err: serde_json::Error) -> Self {
+        SnapshotError::Format(err)
+    }
+}
+
+impl World {
+    pub fn to_json(&self) -> Result<String, SnapshotError> {
+        Ok(serde_json::to_string(&Snapshot{version: SNAPSHOT_VERSION, world: self})?)
+    }
+
+    pub fn from_json(json: &str) -> Result<World, SnapshotError> {
+        from_snapshot(serde_json::from_str(json)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SnapshotError> {
+        write(self, path)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<World, SnapshotError> {
+        read(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    use super::*;
+    use crate::world::{spawn_with_settings, Action, WorldSettings};
+    use crate::world::player::PlayerState;
+    use crate::world::position::{Coord, Offset};
+
+    // Seeded random play, leaving the actions of the last round queued
+    fn played(seed: u64, ticks: usize) -> World {
+        let mut settings = WorldSettings::new();
+        settings.verbose = false;
+        let players: Vec<String> = ["Ann", "Bo", "Cy"].iter().map(|name| String::from(*name)).collect();
+        let mut world = spawn_with_settings(settings, Coord{x: 24, y: 12}, 3, &players, seed);
+        let mut rng = StdRng::seed_from_u64(seed);
+        for tick in 0..=ticks {
+            for uid in 1..=3 {
+                let action = match world.players[&uid].state {
+                    PlayerState::Flying if rng.gen_bool(0.2) => Action::Drop(uid),
+                    PlayerState::Falling(..) => Action::Fly(uid, Offset{x: rng.gen_range(-1, 2), y: rng.gen_range(-1, 2)}),
+                    PlayerState::Boarded => {
+                        let moves = world.legal_moves(uid);
+                        if moves.is_empty() { continue; }
+                        let (from, to) = moves[rng.gen_range(0, moves.len())];
+                        Action::Move(uid, from, to)
+                    },
+                    _ => continue,
+                };
+                world.request_action(action).ok();
+            }
+            if tick < ticks { world.do_tick(); }
+        }
+        world
+    }
+
+    #[test]
+    fn saved_world_loads_and_plays_on_the_same() {
+        let path = env::temp_dir().join(format!("chess_br_snapshot_{}.json", process::id()));
+        for seed in 0..5 {
+            let mut world = played(seed, 30);
+            assert!(!world.req_board_action.is_empty() || !world.req_air_action.is_empty());
+            assert!(world.pieces.values().any(|piece| !piece.unmoved()));
+            world.save(&path).unwrap();
+            let mut loaded = World::load(&path).unwrap();
+            assert_eq!(world.to_json().unwrap(), loaded.to_json().unwrap(), "seed {}", seed);
+            for _ in 0..200 {
+                world.do_tick();
+                loaded.do_tick();
+            }
+            assert_eq!(world.to_json().unwrap(), loaded.to_json().unwrap(), "seed {}", seed);
+        }
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let json = played(1, 10).to_json().unwrap();
+        let version = format!("\"version\":{}", SNAPSHOT_VERSION);
+        assert!(json.starts_with(&format!("{{{}", version)));
+        let json = json.replacen(&version, "\"version\":99", 1);
+        assert!(matches!(World::from_json(&json), Err(SnapshotError::Version(99))));
+    }
+}